@@ -11,7 +11,7 @@ use limnus_local_resource::prelude::LocalResource;
 use limnus_resource::ResourceStorage;
 use limnus_system_params::{LoRe, LoReM, Re};
 use mireforge_game_assets::GameAssets;
-use mireforge_game_audio::GameAudio;
+use mireforge_game_audio::{GameAudio, Voices};
 use monotonic_time_rs::{InstantMonotonicClock, MonotonicClock};
 use std::fmt::{Debug, Formatter};
 use std::marker::PhantomData;
@@ -26,16 +26,18 @@ impl<A: ApplicationAudio<L>, L: ApplicationLogic> Debug for GameAudioRender<A, L
 pub fn advanced_game_audio_tick<R: ApplicationAudio<L>, L: ApplicationLogic>(
     mut audio_render: LoReM<GameAudioRender<R, L>>,
     logic: LoRe<GameLogic<L>>,
-    mut audio_mixer: LoReM<AudioMixer>,
+    mut voices: LoReM<Voices>,
     stereo_samples: Re<limnus_assets::Assets<StereoSample>>,
 ) {
-    let mut game_audio = GameAudio::new(&mut audio_mixer, &stereo_samples);
+    voices.update(audio_render.clock.now());
+    let mut game_audio = GameAudio::new(&mut voices, &stereo_samples);
     audio_render.audio.audio(&mut game_audio, &logic.logic);
 }
 
 #[derive(LocalResource)]
 pub struct GameAudioRender<A: ApplicationAudio<L>, L: ApplicationLogic> {
     audio: A,
+    clock: InstantMonotonicClock,
     _phantom: PhantomData<L>,
 }
 
@@ -45,6 +47,7 @@ impl<A: ApplicationAudio<L>, L: ApplicationLogic> GameAudioRender<A, L> {
         let mut assets = GameAssets::new(all_resources, clock.now());
         Self {
             audio: A::new(&mut assets),
+            clock,
             _phantom: PhantomData,
         }
     }
@@ -70,6 +73,8 @@ impl<A: ApplicationAudio<L>, L: ApplicationLogic> Plugin for GameAudioRenderPlug
         let all_resources = app.resources_mut();
         let internal_audio = GameAudioRender::<A, L>::new(all_resources);
         app.insert_local_resource(internal_audio);
+        let voices = Voices::new(app.local_resources().fetch::<AudioMixer>());
+        app.insert_local_resource(voices);
 
         app.add_system(FixedUpdate, advanced_game_audio_tick::<A, L>);
     }
@@ -10,9 +10,11 @@ pub mod render;
 
 use fixed32::Fp;
 use int_math::{UVec2, Vec2};
-use limnus_basic_input::prelude::{ButtonState, KeyCode, MouseButton};
+use limnus_basic_input::prelude::{
+    ButtonState, KeyCode, MouseButton, MouseScrollDelta, TouchPhase,
+};
 use limnus_gamepad::{Axis, Button, GamePadId, Gamepad};
-use mireforge_game_assets::Assets;
+use mireforge_game_assets::{Assets, wheel_steps};
 use mireforge_game_audio::Audio;
 use mireforge_render_wgpu::prelude::Gfx;
 
@@ -25,6 +27,12 @@ pub trait ApplicationLogic: Sized + 'static {
         false
     }
 
+    /// Polled every tick. `Some(true)` switches the window to borderless fullscreen and
+    /// `Some(false)` back to windowed, e.g. for an F11 toggle. `None` leaves it as it is.
+    fn wants_fullscreen(&self) -> Option<bool> {
+        None
+    }
+
     fn keyboard_input(&mut self, _state: ButtonState, _key_code: KeyCode) {}
 
     fn cursor_entered(&mut self) {}
@@ -33,12 +41,34 @@ pub trait ApplicationLogic: Sized + 'static {
 
     fn cursor_moved(&mut self, _position: UVec2) {}
 
+    /// Same as `cursor_moved`, but the position is converted to world coordinates
+    /// using the current camera.
+    fn cursor_moved_world(&mut self, _position: Vec2) {}
+
+    /// `WindowMessage::Touch` does not carry a finger id, so concurrent touches
+    /// are reported through the same call.
+    fn touch(&mut self, _position: UVec2, _touch_phase: &TouchPhase) {}
+
     fn mouse_input(&mut self, _state: ButtonState, _button: MouseButton) {}
 
     fn mouse_wheel(&mut self, _delta_y: i16) {}
 
+    /// Both axes of a wheel or touchpad scroll, in lines or pixels as reported by the platform.
+    /// The default forwards line deltas to `mouse_wheel`, see [`wheel_steps`].
+    fn mouse_scroll(&mut self, delta: MouseScrollDelta) {
+        if let Some(delta_y) = wheel_steps(delta) {
+            self.mouse_wheel(delta_y);
+        }
+    }
+
+    /// Relative mouse motion, for mouse-look. Not called yet, since `limnus-window-runner` drops
+    /// the motion events, see TODO.md. The delta is meant to be unscaled, the game applies its
+    /// own sensitivity.
     fn mouse_motion(&mut self, _delta: Vec2) {}
 
+    /// Called when a gamepad is connected, before it is activated by its first button press.
+    /// Only activated gamepads are reported to `gamepad_disconnected`.
+    fn gamepad_connected(&mut self, _gamepad_id: GamePadId, _name: &str) {}
     fn gamepad_activated(&mut self, _gamepad_id: GamePadId, _name: String) {}
     fn gamepad_button_changed(&mut self, _gamepad: &Gamepad, _button: Button, _value: Fp) {}
     fn gamepad_axis_changed(&mut self, _gamepad: &Gamepad, _axis: Axis, _value: Fp) {}
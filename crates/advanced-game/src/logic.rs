@@ -4,16 +4,17 @@
  */
 use crate::ApplicationLogic;
 use fixed32::Fp;
-use int_math::{URect, UVec2};
+use int_math::UVec2;
 use limnus_app::prelude::{App, AppReturnValue, ApplicationExit, Plugin};
 use limnus_basic_input::InputMessage;
-use limnus_basic_input::prelude::MouseScrollDelta;
+use limnus_basic_input::prelude::TouchPhase;
 use limnus_default_stages::{FixedUpdate, Update};
 use limnus_gamepad::{GamepadMessage, Gamepads};
 use limnus_local_resource::prelude::LocalResource;
 use limnus_message::MessagesIterator;
-use limnus_screen::WindowMessage;
+use limnus_screen::{Window, WindowMessage};
 use limnus_system_params::{LoReM, Msg, Re, ReAll};
+use mireforge_game::set_fullscreen;
 use mireforge_render_wgpu::Render;
 use std::cmp::{max, min};
 use std::fmt::{Debug, Formatter};
@@ -40,21 +41,16 @@ impl<L: ApplicationLogic> GameLogic<L> {
                     self.logic.mouse_input(*button_state, *button);
                 }
                 InputMessage::MouseWheel(scroll_delta, _touch_phase) => {
-                    if let MouseScrollDelta::LineDelta(delta) = scroll_delta {
-                        let game_scroll_y = (f32::from(-delta.y) * 120.0) as i16;
-                        self.logic.mouse_wheel(game_scroll_y);
-                    }
+                    self.logic.mouse_scroll(*scroll_delta);
                 }
             }
         }
     }
 
-    pub fn cursor_moved(
-        &mut self,
-        physical_position: UVec2,
-        viewport: URect,
-        virtual_surface_size: UVec2,
-    ) {
+    fn virtual_position_from_physical(physical_position: UVec2, wgpu_render: &Render) -> UVec2 {
+        let viewport = wgpu_render.viewport();
+        let virtual_surface_size = wgpu_render.virtual_surface_size_with_scaling();
+
         let relative_x = max(
             0,
             min(
@@ -81,21 +77,35 @@ impl<L: ApplicationLogic> GameLogic<L> {
             * u64::from(virtual_surface_size.y))
             / u64::from(viewport.size.y);
 
-        let virtual_position = UVec2::new(virtual_position_x as u16, virtual_position_y as u16);
+        UVec2::new(virtual_position_x as u16, virtual_position_y as u16)
+    }
+
+    pub fn cursor_moved(&mut self, physical_position: UVec2, wgpu_render: &Render) {
+        let virtual_position = Self::virtual_position_from_physical(physical_position, wgpu_render);
         self.logic.cursor_moved(virtual_position);
+        self.logic
+            .cursor_moved_world(wgpu_render.virtual_to_world(virtual_position));
+    }
+
+    pub fn touch(
+        &mut self,
+        physical_position: UVec2,
+        touch_phase: &TouchPhase,
+        wgpu_render: &Render,
+    ) {
+        let virtual_position = Self::virtual_position_from_physical(physical_position, wgpu_render);
+        self.logic.touch(virtual_position, touch_phase);
     }
 
     pub fn mouse_move(&mut self, iter: MessagesIterator<WindowMessage>, wgpu_render: &Render) {
         for message in iter {
             match message {
-                WindowMessage::CursorMoved(position) => self.cursor_moved(
-                    *position,
-                    wgpu_render.viewport(),
-                    wgpu_render.virtual_surface_size_with_scaling(),
-                ),
+                WindowMessage::CursorMoved(position) => self.cursor_moved(*position, wgpu_render),
                 WindowMessage::WindowCreated() => {}
                 WindowMessage::Resized(_) => {}
-                &WindowMessage::Touch(_, _) => todo!(),
+                WindowMessage::Touch(position, touch_phase) => {
+                    self.touch(*position, touch_phase, wgpu_render);
+                }
             }
         }
     }
@@ -112,6 +122,9 @@ pub fn advanced_game_logic_tick<L: ApplicationLogic>(
     mut game_logic: LoReM<GameLogic<L>>,
 ) {
     game_logic.logic.tick();
+    if let Some(fullscreen) = game_logic.logic.wants_fullscreen() {
+        set_fullscreen(all_resources.fetch_mut::<Window>(), fullscreen);
+    }
     if game_logic.logic.wants_to_quit() {
         all_resources.insert(ApplicationExit {
             value: AppReturnValue::Value(0),
@@ -141,7 +154,11 @@ pub fn advanced_gamepad_input_tick<L: ApplicationLogic>(
 ) {
     for gamepad_message in gamepad_messages.iter_current() {
         match gamepad_message {
-            GamepadMessage::Connected(_gamepad_id, _gamepad_name) => {}
+            GamepadMessage::Connected(gamepad_id, gamepad_name) => {
+                internal_game
+                    .logic
+                    .gamepad_connected(*gamepad_id, gamepad_name);
+            }
             GamepadMessage::Disconnected(gamepad_id) => {
                 if let Some(gamepad) = gamepads.gamepad(*gamepad_id)
                     && gamepad.is_active
@@ -26,7 +26,7 @@ pub fn run<T: Application>(
             minimal_surface_size: virtual_size,
             mode: ScreenMode::Windowed,
         })
-        .insert_resource(GameSettings { virtual_size })
+        .insert_resource(GameSettings::new(virtual_size))
         .add_plugins((limnus::DefaultPlugins, DefaultPlugins))
         .add_plugins(GamePlugin::<T>::new())
         .run()
@@ -37,5 +37,7 @@ pub struct DefaultPlugins;
 impl Plugin for DefaultPlugins {
     fn build(&self, app: &mut App) {
         app.add_plugins((RenderWgpuPlugin, MaterialPlugin, FontPlugin));
+        #[cfg(all(feature = "hot-reload", not(target_arch = "wasm32")))]
+        app.add_plugins(mireforge_game_assets::hot_reload::HotReloadPlugin);
     }
 }
@@ -84,7 +84,7 @@ impl Application for AnimationExample {
     fn audio(&mut self, audio: &mut impl Audio) {
         if self.attack_anim.is_playing() {
             if self.attack_sound.is_none() {
-                self.attack_sound = Some(audio.play(&self.whoosh_sound));
+                self.attack_sound = Some(audio.play(&self.whoosh_sound, Bus::Sfx, 1.0, 0.0));
             }
         } else {
             self.attack_sound = None;
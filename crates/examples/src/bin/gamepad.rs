@@ -212,7 +212,7 @@ impl ApplicationAudio<ExampleLogic> for ExampleAudio {
                     self.attack_sound = None;
                 }
             } else {
-                self.attack_sound = Some(audio.play(&self.whoosh_sound));
+                self.attack_sound = Some(audio.play(&self.whoosh_sound, Bus::Sfx, 1.0, 0.0));
                 self.attack_sound_ticks = 0;
                 self.attack_sound_played_id = state.attack_id;
             }
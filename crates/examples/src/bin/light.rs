@@ -50,12 +50,14 @@ impl Application for LightExample {
             texture_size: UVec2 { x: 0, y: 0 },
             texture_pos: UVec2 { x: 0, y: 0 },
             scale: 1,
+            scale_f32: None,
             rotation: Rotation::default(),
             flip_x: false,
             flip_y: false,
             pivot: Vec2 { y: 0, x: 0 },
             color: background_color,
             anchor: Anchor::LowerLeft,
+            ..Default::default()
         };
 
         gfx.draw_sprite_ex(
@@ -74,12 +76,14 @@ impl Application for LightExample {
                 texture_size: UVec2 { x: 0, y: 0 },
                 texture_pos: UVec2 { x: 0, y: 0 },
                 scale: 1,
+                scale_f32: None,
                 rotation: Rotation::default(),
                 flip_x: false,
                 flip_y: false,
                 pivot: Vec2 { x: 0, y: 0 },
                 color,
                 anchor: Anchor::LowerLeft,
+                ..Default::default()
             };
             gfx.draw_sprite_ex((x, y, 0).into(), &self.light, &sprite_params);
         }
@@ -2,8 +2,11 @@
  * Copyright (c) Peter Bjorklund. All rights reserved. https://github.com/mireforge/mireforge
  * Licensed under the MIT License. See LICENSE in the project root for license information.
  */
-use bmf_parser::BMFont;
-use int_math::{URect, UVec2, Vec2};
+pub mod ttf;
+
+use crate::ttf::FontAtlasImage;
+use bmf_parser::{BMFont, Char};
+use int_math::{Rect, URect, UVec2, Vec2};
 use limnus_app::prelude::{App, Plugin};
 use limnus_asset_registry::AssetRegistry;
 use limnus_assets::Assets;
@@ -12,15 +15,44 @@ use limnus_assets_loader::{
     AssetLoader, ConversionError, ResourceStorage, WrappedAssetLoaderRegistry,
 };
 use limnus_local_resource::LocalResourceStorage;
+use limnus_resource::prelude::Resource;
+use std::collections::HashMap;
 use std::str::FromStr;
 use tracing::debug;
 
 pub type FontRef = Id<Font>;
 pub type WeakFontRef = WeakId<Font>;
 
+const DEFAULT_PIXEL_SIZE: f32 = 16.0;
+const DEFAULT_FALLBACK_GLYPH: char = '?';
+const DEFAULT_TAB_WIDTH: u16 = 4;
+
 #[derive(Debug, Asset)]
 pub struct Font {
     font: BMFont,
+    atlas: Option<FontAtlasImage>,
+    /// Adjustment of the advance for the second char of each pair, from `BMFont::kernings`.
+    kernings: HashMap<(u32, u32), i16>,
+    fallback_glyph: Option<char>,
+    /// Distance between tab stops, in space advances.
+    tab_width: u16,
+}
+
+/// The pixel size to rasterize each `.ttf` and `.otf` font at, set before the font is loaded.
+#[derive(Debug, Default, Resource)]
+pub struct FontRasterSizes {
+    sizes: HashMap<RawWeakId, f32>,
+}
+
+impl FontRasterSizes {
+    pub fn set(&mut self, id: RawWeakId, pixel_size: f32) {
+        self.sizes.insert(id, pixel_size);
+    }
+
+    #[must_use]
+    pub fn get(&self, id: RawWeakId) -> Option<f32> {
+        self.sizes.get(&id).copied()
+    }
 }
 
 pub struct FontPlugin;
@@ -35,6 +67,7 @@ impl Plugin for FontPlugin {
         }
 
         app.insert_resource(Assets::<Font>::default());
+        app.insert_resource(FontRasterSizes::default());
     }
 }
 
@@ -68,9 +101,19 @@ impl AssetLoader for FontConverter {
 
         debug!("convert from fnt {name}");
 
+        let mut atlas = None;
         let font = if name.value().ends_with(".txt.fnt") {
             let str = String::from_utf8(octets.to_vec()).unwrap();
             BMFont::from_str(&str)?
+        } else if name.value().ends_with(".ttf") || name.value().ends_with(".otf") {
+            let pixel_size = resources
+                .get::<FontRasterSizes>()
+                .and_then(|sizes| sizes.get(id))
+                .unwrap_or(DEFAULT_PIXEL_SIZE);
+            let (font, atlas_image) = ttf::rasterize(octets, pixel_size, ttf::DEFAULT_CHARS)
+                .map_err(|_| ConversionError::WrongFormat)?;
+            atlas = Some(atlas_image);
+            font
         } else {
             BMFont::from_octets(octets)?
         };
@@ -78,7 +121,7 @@ impl AssetLoader for FontConverter {
         debug!("font complete {name}");
         let font_assets = resources.fetch_mut::<Assets<Font>>();
 
-        font_assets.set_raw(id, Font { font });
+        font_assets.set_raw(id, Font::new(font, atlas));
 
         Ok(())
     }
@@ -93,6 +136,10 @@ pub struct GlyphInfo {
 
 #[derive(Debug)]
 pub struct Glyph {
+    /// Index of the char in the source text that this glyph was created from.
+    pub char_index: usize,
+    /// Index into [`GlyphDraw::line_widths`] for the line the glyph is on.
+    pub line_index: usize,
     pub relative_position: Vec2,
     pub texture_rectangle: URect,
     pub cursor: Vec2,
@@ -103,6 +150,13 @@ pub struct Glyph {
 pub struct GlyphDraw {
     pub glyphs: Vec<Glyph>,
     pub cursor: Vec2,
+    /// Bounding box of all the lines, relative to the draw position.
+    /// Lines below the first one have a negative y.
+    pub bounds: Rect,
+    /// Advance width of each line, used for aligning lines individually.
+    pub line_widths: Vec<u16>,
+    /// Number of chars that were not in the font, drawn with the fallback glyph if it exists.
+    pub missing_glyphs: usize,
 }
 
 impl Font {
@@ -111,7 +165,85 @@ impl Font {
     #[must_use]
     pub fn from_octets(bm_contents: &[u8]) -> Self {
         let font = BMFont::from_octets(bm_contents).unwrap();
-        Self { font }
+        Self::new(font, None)
+    }
+
+    fn new(font: BMFont, atlas: Option<FontAtlasImage>) -> Self {
+        let kernings = font
+            .kernings
+            .iter()
+            .map(|pair| ((pair.first, pair.second), pair.amount))
+            .collect();
+        Self {
+            font,
+            atlas,
+            kernings,
+            fallback_glyph: Some(DEFAULT_FALLBACK_GLYPH),
+            tab_width: DEFAULT_TAB_WIDTH,
+        }
+    }
+
+    /// Sets the distance between tab stops as a number of space advances, 4 by default.
+    pub const fn set_tab_width(&mut self, spaces: u16) {
+        self.tab_width = spaces;
+    }
+
+    #[must_use]
+    pub const fn tab_width(&self) -> u16 {
+        self.tab_width
+    }
+
+    fn tab_width_in_pixels(&self) -> i32 {
+        i32::from(self.tab_width) * self.advance(' ')
+    }
+
+    /// The x of the first tab stop after `x`.
+    fn next_tab_stop(&self, x: i32) -> i32 {
+        let tab_width = self.tab_width_in_pixels();
+        if tab_width <= 0 {
+            return x;
+        }
+        (x.div_euclid(tab_width) + 1) * tab_width
+    }
+
+    /// Sets the glyph that is drawn for chars that are not in the font, `'?'` by default.
+    /// With `None`, missing chars are skipped without advancing.
+    pub const fn set_fallback_glyph(&mut self, fallback_glyph: Option<char>) {
+        self.fallback_glyph = fallback_glyph;
+    }
+
+    #[must_use]
+    pub const fn fallback_glyph(&self) -> Option<char> {
+        self.fallback_glyph
+    }
+
+    /// Looks up the glyph for the unicode scalar value of `ch`, or the fallback glyph.
+    /// The `bool` is `true` if `ch` itself is missing from the font.
+    fn glyph_or_fallback(&self, ch: char) -> (Option<&Char>, bool) {
+        self.font.chars.get(&u32::from(ch)).map_or_else(
+            || {
+                let fallback = self
+                    .fallback_glyph
+                    .and_then(|fallback| self.font.chars.get(&u32::from(fallback)));
+                (fallback, true)
+            },
+            |bm_char| (Some(bm_char), false),
+        )
+    }
+
+    /// The kerning between `previous` and `ch`, added to the advance of `previous`.
+    #[must_use]
+    pub fn kerning(&self, previous: char, ch: char) -> i16 {
+        self.kernings
+            .get(&(u32::from(previous), u32::from(ch)))
+            .copied()
+            .unwrap_or(0)
+    }
+
+    /// Takes the glyph atlas of a rasterized `.ttf` or `.otf` font, so it can be uploaded
+    /// as the texture of the font. `BMFont` fonts have their atlas in a separate png.
+    pub const fn take_atlas(&mut self) -> Option<FontAtlasImage> {
+        self.atlas.take()
     }
 
     #[must_use]
@@ -119,22 +251,79 @@ impl Font {
         &self.font
     }
 
+    /// Distance between the baselines of two lines, in pixels.
+    ///
+    /// # Panics
+    ///
+    #[must_use]
+    pub fn line_height(&self) -> u16 {
+        self.font.common.as_ref().unwrap().line_height
+    }
+
+    /// Distance from the top of a line to the baseline, in pixels.
+    ///
+    /// # Panics
+    ///
+    #[must_use]
+    pub fn baseline(&self) -> u16 {
+        self.font.common.as_ref().unwrap().base
+    }
+
+    /// The size the font was generated or rasterized at. Falls back to the line height
+    /// if the font has no info block.
+    #[must_use]
+    pub fn size(&self) -> u16 {
+        self.font
+            .info
+            .as_ref()
+            .map_or_else(|| self.line_height(), |info| info.font_size.unsigned_abs())
+    }
+
+    /// Lays out the glyphs for `text`. A `\n` moves the cursor to the start of the next line,
+    /// `line_height` below the current one, and a `\t` moves it to the next tab stop.
+    ///
     /// # Panics
     ///
     #[must_use]
     pub fn draw(&self, text: &str) -> GlyphDraw {
         let mut x = 0;
-        let y = 0;
+        let mut y = 0;
         let common = self.font.common.as_ref().unwrap();
         let mut glyphs = Vec::new();
         let factor = 1u16;
         let y_offset = (common.base as i16) + 1;
-        for ch in text.chars() {
-            if let Some(bm_char) = self.font.chars.get(&(ch as u32)) {
+        let line_height = common.line_height as i16;
+        let mut line_widths = Vec::new();
+        let mut previous = None;
+        let mut missing_glyphs = 0;
+        for (char_index, ch) in text.chars().enumerate() {
+            if ch == '\n' {
+                line_widths.push(x.max(0) as u16);
+                x = 0;
+                y -= line_height;
+                previous = None;
+                continue;
+            }
+            if ch == '\t' {
+                x = self.next_tab_stop(i32::from(x)) as i16;
+                previous = None;
+                continue;
+            }
+            let (found_char, is_missing) = self.glyph_or_fallback(ch);
+            if is_missing {
+                missing_glyphs += 1;
+            }
+            if let Some(bm_char) = found_char {
+                if let Some(previous) = previous {
+                    x += self.kerning(previous, ch);
+                }
+                previous = Some(ch);
                 let cx = x + bm_char.x_offset * factor as i16;
                 let cy = y + y_offset - (bm_char.height as i16) - bm_char.y_offset;
 
                 let glyph = Glyph {
+                    char_index,
+                    line_index: line_widths.len(),
                     relative_position: Vec2 { x: cx, y: cy },
                     texture_rectangle: URect {
                         position: UVec2 {
@@ -159,9 +348,208 @@ impl Font {
             }
         }
 
+        line_widths.push(x.max(0) as u16);
+        let max_line_width = line_widths.iter().copied().max().unwrap_or(0);
+
         GlyphDraw {
             glyphs,
             cursor: Vec2::new(x, y),
+            bounds: Rect::new(
+                0,
+                y,
+                max_line_width,
+                (line_widths.len() as i16 * line_height) as u16,
+            ),
+            line_widths,
+            missing_glyphs,
+        }
+    }
+
+    /// Returns the size of the bounding box that [`Self::draw`] would report for `text`,
+    /// without laying out any glyphs.
+    ///
+    /// # Panics
+    ///
+    #[must_use]
+    pub fn measure(&self, text: &str) -> UVec2 {
+        let common = self.font.common.as_ref().unwrap();
+        let mut line_width = 0;
+        let mut max_line_width = 0;
+        let mut line_count = 1;
+        let mut previous = None;
+        for ch in text.chars() {
+            if ch == '\n' {
+                max_line_width = max_line_width.max(line_width);
+                line_width = 0;
+                line_count += 1;
+                previous = None;
+                continue;
+            }
+            if ch == '\t' {
+                line_width = self.next_tab_stop(line_width);
+                previous = None;
+                continue;
+            }
+            if let Some(bm_char) = self.glyph_or_fallback(ch).0 {
+                if let Some(previous) = previous {
+                    line_width += i32::from(self.kerning(previous, ch));
+                }
+                previous = Some(ch);
+                line_width += i32::from(bm_char.x_advance);
+            }
+        }
+        max_line_width = max_line_width.max(line_width);
+
+        UVec2::new(
+            max_line_width as u16,
+            (line_count * u32::from(common.line_height)) as u16,
+        )
+    }
+
+    /// Same as [`Self::draw`], but inserts line breaks at spaces so that no line is wider
+    /// than `max_width`. Words that are wider than `max_width` are broken at the character
+    /// that would overflow.
+    #[must_use]
+    pub fn draw_wrapped(&self, text: &str, max_width: u16) -> GlyphDraw {
+        self.draw(&self.wrap(text, max_width))
+    }
+
+    fn wrap(&self, text: &str, max_width: u16) -> String {
+        let max_width = i32::from(max_width);
+        let space_width = self.advance(' ');
+        let mut wrapped = String::with_capacity(text.len() + 8);
+
+        for (line_index, line) in text.split('\n').enumerate() {
+            if line_index > 0 {
+                wrapped.push('\n');
+            }
+            let mut line_width = 0;
+            for (word_index, word) in line.split(' ').enumerate() {
+                if word_index > 0 {
+                    let word_width: i32 = word.chars().map(|ch| self.advance(ch)).sum();
+                    if line_width + space_width + word_width <= max_width {
+                        wrapped.push(' ');
+                        line_width += space_width;
+                    } else {
+                        wrapped.push('\n');
+                        line_width = 0;
+                    }
+                }
+
+                for ch in word.chars() {
+                    let advance = self.advance(ch);
+                    if line_width > 0 && line_width + advance > max_width {
+                        // Hard break, the word is wider than the whole line
+                        wrapped.push('\n');
+                        line_width = 0;
+                    }
+                    wrapped.push(ch);
+                    line_width += advance;
+                }
+            }
+        }
+
+        wrapped
+    }
+
+    fn advance(&self, ch: char) -> i32 {
+        if ch == '\t' {
+            // Only used for wrapping, where the position is not known, so assume a full tab
+            return self.tab_width_in_pixels();
         }
+        self.glyph_or_fallback(ch)
+            .0
+            .map_or(0, |bm_char| i32::from(bm_char.x_advance))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use bmf_parser::{CommonBlock, KerningPair};
+
+    fn glyph(ch: char, x_advance: i16) -> (u32, Char) {
+        (
+            u32::from(ch),
+            Char {
+                id: u32::from(ch),
+                x: 0,
+                y: 0,
+                width: 8,
+                height: 8,
+                x_offset: 0,
+                y_offset: 0,
+                x_advance,
+                page: 0,
+                chnl: 15,
+            },
+        )
+    }
+
+    fn font_with_kerning(first: char, second: char, amount: i16) -> Font {
+        let bm_font = BMFont {
+            info: None,
+            common: Some(CommonBlock {
+                line_height: 12,
+                base: 10,
+                scale_w: 64,
+                scale_h: 64,
+                pages: 1,
+                bit_field: 0,
+                alpha_chnl: 0,
+                red_chnl: 4,
+                green_chnl: 4,
+                blue_chnl: 4,
+            }),
+            pages: Vec::new(),
+            chars: HashMap::from([glyph('A', 10), glyph('V', 10)]),
+            kernings: vec![KerningPair {
+                first: u32::from(first),
+                second: u32::from(second),
+                amount,
+            }],
+        };
+        Font::new(bm_font, None)
+    }
+
+    #[test]
+    fn kerning_adjusts_the_advance_of_the_pair() {
+        let font = font_with_kerning('A', 'V', -2);
+        assert_eq!(font.kerning('A', 'V'), -2);
+        assert_eq!(font.kerning('V', 'A'), 0);
+
+        let glyph_draw = font.draw("AV");
+        assert_eq!(glyph_draw.glyphs[0].relative_position.x, 0);
+        assert_eq!(glyph_draw.glyphs[1].relative_position.x, 8);
+        assert_eq!(glyph_draw.line_widths, vec![18]);
+        assert_eq!(font.measure("AV").x, 18);
+    }
+
+    #[test]
+    fn kerning_only_applies_in_order() {
+        let font = font_with_kerning('A', 'V', -2);
+        let glyph_draw = font.draw("VA");
+        assert_eq!(glyph_draw.glyphs[1].relative_position.x, 10);
+        assert_eq!(glyph_draw.line_widths, vec![20]);
+    }
+
+    #[test]
+    fn measure_matches_the_bounds_of_draw() {
+        let mut font = font_with_kerning('A', 'V', -2);
+        for fallback_glyph in [None, Some('A')] {
+            font.set_fallback_glyph(fallback_glyph);
+            for text in ["AV", "VA", "A?V", "?AV?", "AV\nA?V\tA", ""] {
+                let size = font.draw(text).bounds.size;
+                assert_eq!(font.measure(text), size, "{text:?} with {fallback_glyph:?}");
+            }
+        }
+    }
+
+    #[test]
+    fn negative_line_width_is_clamped_to_zero() {
+        let font = font_with_kerning('A', 'V', -30);
+        let glyph_draw = font.draw("AV\nA");
+        assert_eq!(glyph_draw.line_widths, vec![0, 10]);
+        assert_eq!(glyph_draw.bounds.size.x, 10);
     }
 }
@@ -0,0 +1,211 @@
+/*
+ * Copyright (c) Peter Bjorklund. All rights reserved. https://github.com/mireforge/mireforge
+ * Licensed under the MIT License. See LICENSE in the project root for license information.
+ */
+use ab_glyph::{Font as _, FontVec, PxScale, ScaleFont, point};
+use bmf_parser::{BMFont, Char, CommonBlock, InfoBlock, KerningPair};
+use int_math::UVec2;
+use std::collections::HashMap;
+
+const ATLAS_WIDTH: u32 = 512;
+const GLYPH_PADDING: u32 = 1;
+
+/// Printable ASCII and Latin-1, used when no other chars are requested.
+pub const DEFAULT_CHARS: std::ops::RangeInclusive<char> = ' '..='ÿ';
+
+/// Rasterized glyphs for a vector font. White, with the coverage in alpha.
+#[derive(Debug)]
+pub struct FontAtlasImage {
+    pub size: UVec2,
+    pub rgba: Vec<u8>,
+}
+
+#[derive(Debug)]
+pub enum RasterizeError {
+    InvalidFont,
+    /// The glyphs do not fit in an atlas with `u16` dimensions.
+    AtlasTooLarge,
+}
+
+struct RasterizedGlyph {
+    ch: char,
+    size: UVec2,
+    offset: (i16, i16),
+    advance: i16,
+    coverage: Vec<u8>,
+}
+
+/// Rasterizes `chars` of a `.ttf` or `.otf` font at `pixel_size` into an atlas, and describes
+/// the glyphs the same way as a `BMFont`, so the layout is the same for both kinds of fonts.
+///
+/// # Errors
+/// If the octets are not a valid font, or the glyphs at `pixel_size` do not fit in an atlas.
+pub fn rasterize(
+    octets: &[u8],
+    pixel_size: f32,
+    chars: impl Iterator<Item = char>,
+) -> Result<(BMFont, FontAtlasImage), RasterizeError> {
+    let font = FontVec::try_from_vec(octets.to_vec()).map_err(|_| RasterizeError::InvalidFont)?;
+    let scaled_font = font.as_scaled(PxScale::from(pixel_size));
+    let base = scaled_font.ascent().ceil();
+
+    let mut glyphs = Vec::new();
+    for ch in chars {
+        let glyph_id = font.glyph_id(ch);
+        if glyph_id.0 == 0 && ch != ' ' {
+            continue;
+        }
+        let advance = scaled_font.h_advance(glyph_id).round() as i16;
+        let glyph = glyph_id.with_scale_and_position(pixel_size, point(0.0, base));
+
+        let rasterized = scaled_font.outline_glyph(glyph).map_or_else(
+            || RasterizedGlyph {
+                ch,
+                size: UVec2::new(0, 0),
+                offset: (0, 0),
+                advance,
+                coverage: Vec::new(),
+            },
+            |outlined| {
+                let bounds = outlined.px_bounds();
+                let width = bounds.width() as u16;
+                let height = bounds.height() as u16;
+                let mut coverage = vec![0u8; usize::from(width) * usize::from(height)];
+                outlined.draw(|x, y, value| {
+                    let index = y as usize * usize::from(width) + x as usize;
+                    if let Some(pixel) = coverage.get_mut(index) {
+                        *pixel = (value.clamp(0.0, 1.0) * 255.0).round() as u8;
+                    }
+                });
+                RasterizedGlyph {
+                    ch,
+                    size: UVec2::new(width, height),
+                    // `y` is measured from the top of the line, like `BMFont`
+                    offset: (bounds.min.x as i16, bounds.min.y as i16),
+                    advance,
+                    coverage,
+                }
+            },
+        );
+        glyphs.push(rasterized);
+    }
+
+    // Simple row packing, the glyphs are in the same order as the requested chars.
+    // The atlas is widened if a single glyph does not fit in the default width.
+    let widest = glyphs
+        .iter()
+        .map(|glyph| u32::from(glyph.size.x))
+        .max()
+        .unwrap_or(0);
+    let atlas_width = ATLAS_WIDTH.max((widest + 2 * GLYPH_PADDING).next_power_of_two());
+    let mut positions = Vec::with_capacity(glyphs.len());
+    let (mut cursor_x, mut cursor_y) = (GLYPH_PADDING, GLYPH_PADDING);
+    let mut row_height = 0;
+    for glyph in &glyphs {
+        let (width, height) = (u32::from(glyph.size.x), u32::from(glyph.size.y));
+        if cursor_x + width + GLYPH_PADDING > atlas_width {
+            cursor_x = GLYPH_PADDING;
+            cursor_y += row_height + GLYPH_PADDING;
+            row_height = 0;
+        }
+        positions.push((cursor_x, cursor_y));
+        cursor_x += width + GLYPH_PADDING;
+        row_height = row_height.max(height);
+    }
+    let atlas_height = (cursor_y + row_height + GLYPH_PADDING)
+        .checked_next_power_of_two()
+        .ok_or(RasterizeError::AtlasTooLarge)?;
+    let atlas_size = UVec2::new(
+        u16::try_from(atlas_width).map_err(|_| RasterizeError::AtlasTooLarge)?,
+        u16::try_from(atlas_height).map_err(|_| RasterizeError::AtlasTooLarge)?,
+    );
+
+    let mut rgba = vec![0u8; usize::from(atlas_size.x) * usize::from(atlas_size.y) * 4];
+    let mut bm_chars = HashMap::with_capacity(glyphs.len());
+    for (glyph, (position_x, position_y)) in glyphs.iter().zip(positions) {
+        // Every position is inside the atlas, which fits in `u16`
+        let position = UVec2::new(position_x as u16, position_y as u16);
+        for y in 0..usize::from(glyph.size.y) {
+            for x in 0..usize::from(glyph.size.x) {
+                let alpha = glyph.coverage[y * usize::from(glyph.size.x) + x];
+                let index = ((usize::from(position.y) + y) * usize::from(atlas_size.x)
+                    + usize::from(position.x)
+                    + x)
+                    * 4;
+                rgba[index..index + 4].copy_from_slice(&[255, 255, 255, alpha]);
+            }
+        }
+
+        bm_chars.insert(
+            u32::from(glyph.ch),
+            Char {
+                id: u32::from(glyph.ch),
+                x: position.x,
+                y: position.y,
+                width: glyph.size.x,
+                height: glyph.size.y,
+                x_offset: glyph.offset.0,
+                y_offset: glyph.offset.1,
+                x_advance: glyph.advance,
+                page: 0,
+                chnl: 15,
+            },
+        );
+    }
+
+    let mut kernings = Vec::new();
+    for first in &glyphs {
+        for second in &glyphs {
+            let amount = scaled_font
+                .kern(font.glyph_id(first.ch), font.glyph_id(second.ch))
+                .round() as i16;
+            if amount != 0 {
+                kernings.push(KerningPair {
+                    first: u32::from(first.ch),
+                    second: u32::from(second.ch),
+                    amount,
+                });
+            }
+        }
+    }
+
+    let line_height =
+        (scaled_font.ascent() - scaled_font.descent() + scaled_font.line_gap()).ceil() as u16;
+
+    let bm_font = BMFont {
+        info: Some(InfoBlock {
+            font_size: pixel_size.round() as i16,
+            bit_field: 0,
+            char_set: 0,
+            stretch_h: 100,
+            aa: 1,
+            padding: [0; 4],
+            spacing: [GLYPH_PADDING as u8; 2],
+            outline: 0,
+            font_name: String::new(),
+        }),
+        common: Some(CommonBlock {
+            line_height,
+            base: base as u16,
+            scale_w: atlas_size.x,
+            scale_h: atlas_size.y,
+            pages: 1,
+            bit_field: 0,
+            alpha_chnl: 0,
+            red_chnl: 4,
+            green_chnl: 4,
+            blue_chnl: 4,
+        }),
+        pages: Vec::new(),
+        chars: bm_chars,
+        kernings,
+    };
+
+    Ok((
+        bm_font,
+        FontAtlasImage {
+            size: atlas_size,
+            rgba,
+        },
+    ))
+}
@@ -0,0 +1,105 @@
+/*
+ * Copyright (c) Peter Bjorklund. All rights reserved. https://github.com/mireforge/mireforge
+ * Licensed under the MIT License. See LICENSE in the project root for license information.
+ */
+use limnus_app::prelude::{App, Plugin};
+use limnus_asset_id::{AssetName, RawWeakId};
+use limnus_asset_registry::AssetRegistry;
+use limnus_default_stages::First;
+use limnus_resource::prelude::Resource;
+use limnus_system_params::{LoReAll, ReAll, ReM};
+use std::path::PathBuf;
+use std::time::{Duration, Instant, SystemTime};
+use tracing::{debug, warn};
+
+const POLL_INTERVAL: Duration = Duration::from_millis(500);
+
+#[derive(Debug)]
+struct WatchedAsset {
+    id: RawWeakId,
+    path: PathBuf,
+    modified: Option<SystemTime>,
+}
+
+/// Assets that are loaded again when their file is modified.
+///
+/// The new octets are converted by the same loader and replace the asset with the same `Id`,
+/// so materials and fonts that refer to it pick up the change directly.
+#[derive(Debug, Resource)]
+pub struct HotReload {
+    watched: Vec<WatchedAsset>,
+    last_poll_at: Instant,
+}
+
+impl Default for HotReload {
+    fn default() -> Self {
+        Self {
+            watched: Vec::new(),
+            last_poll_at: Instant::now(),
+        }
+    }
+}
+
+impl HotReload {
+    pub fn watch(&mut self, id: RawWeakId, name: AssetName) {
+        let path = PathBuf::from("assets").join(name.value());
+        let modified = modified_time(&path);
+        self.watched.push(WatchedAsset { id, path, modified });
+    }
+
+    fn changed_assets(&mut self) -> Vec<(RawWeakId, PathBuf)> {
+        let mut changed = Vec::new();
+        for watched in &mut self.watched {
+            let modified = modified_time(&watched.path);
+            if modified.is_some() && modified != watched.modified {
+                watched.modified = modified;
+                changed.push((watched.id, watched.path.clone()));
+            }
+        }
+        changed
+    }
+}
+
+fn modified_time(path: &PathBuf) -> Option<SystemTime> {
+    std::fs::metadata(path)
+        .and_then(|metadata| metadata.modified())
+        .ok()
+}
+
+fn hot_reload_tick(
+    mut hot_reload: ReM<HotReload>,
+    mut asset_registry: ReM<AssetRegistry>,
+    mut resources: ReAll,
+    mut local_resources: LoReAll,
+) {
+    if hot_reload.last_poll_at.elapsed() < POLL_INTERVAL {
+        return;
+    }
+    hot_reload.last_poll_at = Instant::now();
+
+    for (id, path) in hot_reload.changed_assets() {
+        let octets = match std::fs::read(&path) {
+            Ok(octets) => octets,
+            Err(err) => {
+                warn!(?path, ?err, "could not read changed asset");
+                continue;
+            }
+        };
+        debug!(?path, "reloading changed asset");
+        if let Err(err) =
+            asset_registry.blob_loaded(id, &octets, &mut resources, &mut local_resources)
+        {
+            warn!(?path, ?err, "could not reload changed asset");
+        }
+    }
+}
+
+/// Watches the files of the assets loaded through `GameAssets`, see [`HotReload`].
+pub struct HotReloadPlugin;
+
+impl Plugin for HotReloadPlugin {
+    fn build(&self, app: &mut App) {
+        app.insert_resource(HotReload::default());
+        app.add_system(First, hot_reload_tick);
+    }
+}
@@ -2,18 +2,27 @@
  * Copyright (c) Peter Bjorklund. All rights reserved. https://github.com/mireforge/mireforge
  * Licensed under the MIT License. See LICENSE in the project root for license information.
  */
-use int_math::UVec2;
+#[cfg(all(feature = "hot-reload", not(target_arch = "wasm32")))]
+pub mod hot_reload;
 
-use limnus_asset_id::{AssetName, Id};
+#[cfg(all(feature = "hot-reload", not(target_arch = "wasm32")))]
+use crate::hot_reload::HotReload;
+use int_math::UVec2;
+use limnus_asset_id::{Asset, AssetName, Id};
 use limnus_asset_registry::AssetRegistry;
 use limnus_audio_mixer::{StereoSample, StereoSampleRef};
+use limnus_basic_input::prelude::{KeyCode, MouseButton, MouseScrollDelta};
+use limnus_gamepad::GamePadId;
 use limnus_resource::ResourceStorage;
-use mireforge_font::{Font, GlyphDraw};
+use limnus_resource::prelude::Resource;
+use mireforge_font::{Font, FontRasterSizes, GlyphDraw};
+use mireforge_render_wgpu::plugin::{FontAtlases, GpuInfo, TextureOptionsByName};
 use mireforge_render_wgpu::{
-    FixedAtlas, FontAndMaterial, Material, MaterialBase, MaterialKind, MaterialRef,
-    NineSliceAndMaterial, Slices, Texture, TextureRef,
+    BlendMode, FixedAtlas, FontAndMaterial, Material, MaterialBase, MaterialKind, MaterialRef,
+    NineSliceAndMaterial, NineSliceMode, Render, Slices, Texture, TextureOptions, TextureRef,
 };
-use monotonic_time_rs::Millis;
+use monotonic_time_rs::{Millis, MillisDuration};
+use std::collections::HashSet;
 use std::fmt::Debug;
 use std::sync::Arc;
 
@@ -21,12 +30,57 @@ pub trait Assets {
     #[must_use]
     fn now(&self) -> Millis;
 
+    /// Time simulated by the current tick.
+    #[must_use]
+    fn delta(&self) -> MillisDuration;
+
     #[must_use]
     fn texture_png(&mut self, name: impl Into<AssetName>) -> TextureRef;
 
     #[must_use]
     fn material_png(&mut self, name: impl Into<AssetName>) -> MaterialRef;
 
+    /// Same as [`Self::material_png`], but uploads the texture with `options`.
+    /// A texture is only loaded once, so all materials of the same png share the options
+    /// of the first load.
+    #[must_use]
+    fn material_png_ex(
+        &mut self,
+        name: impl Into<AssetName>,
+        options: TextureOptions,
+    ) -> MaterialRef;
+
+    /// Creates an offscreen texture of `size` and a sprite material for it.
+    /// Items are rendered to it after `Gfx::set_render_target` with the texture
+    /// of the material.
+    #[must_use]
+    fn render_target_material(&mut self, name: impl Into<AssetName>, size: UVec2) -> MaterialRef;
+
+    /// Creates a texture from tightly packed rgba octets, e.g. procedurally generated.
+    #[must_use]
+    fn texture_from_rgba(
+        &mut self,
+        name: impl Into<AssetName>,
+        rgba: &[u8],
+        size: UVec2,
+    ) -> TextureRef;
+
+    /// Same as [`Self::texture_from_rgba`], but returns a sprite material for the texture.
+    #[must_use]
+    fn material_from_rgba(
+        &mut self,
+        name: impl Into<AssetName>,
+        rgba: &[u8],
+        size: UVec2,
+    ) -> MaterialRef;
+
+    #[must_use]
+    fn material_png_blend(
+        &mut self,
+        name: impl Into<AssetName>,
+        blend_mode: BlendMode,
+    ) -> MaterialRef;
+
     #[must_use]
     fn material_alpha_mask(
         &mut self,
@@ -58,6 +112,11 @@ pub trait Assets {
     #[must_use]
     fn bm_font_txt(&mut self, name: impl Into<AssetName>) -> FontAndMaterial;
 
+    /// Loads a `.ttf` or `.otf` font, `name` including the extension, and rasterizes
+    /// the glyphs at `pixel_size` into a texture for the material.
+    #[must_use]
+    fn ttf_font(&mut self, name: impl Into<AssetName>, pixel_size: f32) -> FontAndMaterial;
+
     #[must_use]
     fn text_glyphs(&self, text: &str, font_and_mat: &FontAndMaterial) -> Option<GlyphDraw>;
 
@@ -65,10 +124,319 @@ pub trait Assets {
     fn font(&self, font_ref: &Id<Font>) -> Option<&Font>;
     #[must_use]
     fn audio_sample_wav(&mut self, name: impl Into<AssetName>) -> StereoSampleRef;
+
+    /// The keys that are currently held down.
+    #[must_use]
+    fn keys(&self) -> &Keys;
+
+    /// The latest cursor position and the mouse buttons that are currently held down.
+    #[must_use]
+    fn mouse(&self) -> &Mouse;
+
+    /// Returns `true` if the gamepad backend forwards rumble requests to the driver.
+    #[must_use]
+    fn supports_rumble(&self) -> bool;
+
+    /// Rumbles the gamepad with the `strong` (low frequency) and `weak` (high frequency)
+    /// motors, both in the range `0.0..=1.0`. Does nothing if rumble is not supported.
+    fn set_rumble(
+        &mut self,
+        gamepad_id: GamePadId,
+        strong: f32,
+        weak: f32,
+        duration: MillisDuration,
+    );
+
+    /// The text on the clipboard, if there is any.
+    fn clipboard_get(&mut self) -> Option<String>;
+
+    /// Puts `text` on the clipboard, e.g. when copying from a text field.
+    fn clipboard_set(&mut self, text: &str);
+
+    /// The gpu that is used for rendering, if the render plugin is running.
+    #[must_use]
+    fn gpu_info(&self) -> Option<&GpuInfo>;
+
+    /// Returns `true` when the asset has been loaded and converted.
+    #[must_use]
+    fn is_loaded<T: Asset>(&self, id: &Id<T>) -> bool;
+
+    /// Returns `true` when all the textures of the material are loaded, so it will be rendered.
+    #[must_use]
+    fn is_material_loaded(&self, material: &MaterialRef) -> bool;
+
+    #[must_use]
+    fn is_font_loaded(&self, font_and_material: &FontAndMaterial) -> bool;
+
+    /// Useful for a loading screen, that waits for a set of materials before starting.
+    #[must_use]
+    fn all_materials_loaded(&self, materials: &[&MaterialRef]) -> bool {
+        materials
+            .iter()
+            .all(|material| self.is_material_loaded(material))
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct RumbleRequest {
+    pub gamepad_id: GamePadId,
+    pub strong: f32,
+    pub weak: f32,
+    pub duration: MillisDuration,
+}
+
+/// Keyboard state, updated from the keyboard input messages.
+///
+/// `just_pressed` and `just_released` are reported to the first tick after the change.
+#[derive(Debug, Default, Resource)]
+pub struct Keys {
+    down: HashSet<KeyCode>,
+    just_pressed: HashSet<KeyCode>,
+    just_released: HashSet<KeyCode>,
+}
+
+impl Keys {
+    #[must_use]
+    pub fn is_key_down(&self, key_code: KeyCode) -> bool {
+        self.down.contains(&key_code)
+    }
+
+    #[must_use]
+    pub fn just_pressed(&self, key_code: KeyCode) -> bool {
+        self.just_pressed.contains(&key_code)
+    }
+
+    #[must_use]
+    pub fn just_released(&self, key_code: KeyCode) -> bool {
+        self.just_released.contains(&key_code)
+    }
+
+    /// The modifier keys that are held down, either the left or the right one.
+    #[must_use]
+    pub fn modifiers(&self) -> Modifiers {
+        let either = |left, right| self.is_key_down(left) || self.is_key_down(right);
+        Modifiers {
+            shift: either(KeyCode::ShiftLeft, KeyCode::ShiftRight),
+            ctrl: either(KeyCode::ControlLeft, KeyCode::ControlRight),
+            alt: either(KeyCode::AltLeft, KeyCode::AltRight),
+            super_key: either(KeyCode::SuperLeft, KeyCode::SuperRight),
+        }
+    }
+
+    pub fn press(&mut self, key_code: KeyCode) {
+        // Key repeats are reported as presses, so only the first one counts
+        if self.down.insert(key_code) {
+            self.just_pressed.insert(key_code);
+        }
+    }
+
+    pub fn release(&mut self, key_code: KeyCode) {
+        if self.down.remove(&key_code) {
+            self.just_released.insert(key_code);
+        }
+    }
+
+    pub fn clear_just_changed(&mut self) {
+        self.just_pressed.clear();
+        self.just_released.clear();
+    }
+}
+
+/// Modifier keys held down, see [`Keys::modifiers`].
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct Modifiers {
+    pub shift: bool,
+    pub ctrl: bool,
+    pub alt: bool,
+    /// Windows or Command key.
+    pub super_key: bool,
+}
+
+impl Modifiers {
+    /// No modifier key is held down.
+    #[must_use]
+    pub const fn is_empty(self) -> bool {
+        !(self.shift || self.ctrl || self.alt || self.super_key)
+    }
+}
+
+/// Mouse state, updated from the cursor and mouse button messages.
+#[derive(Debug, Resource)]
+pub struct Mouse {
+    virtual_position: UVec2,
+    down: HashSet<MouseButton>,
+}
+
+impl Default for Mouse {
+    fn default() -> Self {
+        Self {
+            virtual_position: UVec2::new(0, 0),
+            down: HashSet::new(),
+        }
+    }
+}
+
+impl Mouse {
+    /// The latest cursor position in virtual surface coordinates.
+    #[must_use]
+    pub const fn virtual_position(&self) -> UVec2 {
+        self.virtual_position
+    }
+
+    #[must_use]
+    pub fn is_button_down(&self, button: MouseButton) -> bool {
+        self.down.contains(&button)
+    }
+
+    pub const fn set_virtual_position(&mut self, virtual_position: UVec2) {
+        self.virtual_position = virtual_position;
+    }
+
+    pub fn press(&mut self, button: MouseButton) {
+        self.down.insert(button);
+    }
+
+    pub fn release(&mut self, button: MouseButton) {
+        self.down.remove(&button);
+    }
+}
+
+/// Converts a scroll to the `mouse_wheel` value, 120 per line with the y axis flipped.
+/// Pixel deltas, e.g. from touchpads, have no line steps and give `None`.
+#[must_use]
+pub fn wheel_steps(delta: MouseScrollDelta) -> Option<i16> {
+    match delta {
+        MouseScrollDelta::LineDelta(delta) => Some((f32::from(-delta.y) * 120.0) as i16),
+        MouseScrollDelta::PixelDelta(_) => None,
+    }
+}
+
+/// Rumble requests from the game, waiting to be forwarded to the gamepad driver.
+///
+/// A gamepad backend that can rumble should set `supported` and drain the requests each
+/// update with [`Self::take_requests`].
+#[derive(Debug, Default, Resource)]
+pub struct GamepadRumble {
+    pub supported: bool,
+    requests: Vec<RumbleRequest>,
+}
+
+impl GamepadRumble {
+    pub fn push(&mut self, request: RumbleRequest) {
+        if self.supported {
+            self.requests.push(request);
+        }
+    }
+
+    pub fn take_requests(&mut self) -> Vec<RumbleRequest> {
+        std::mem::take(&mut self.requests)
+    }
+}
+
+/// Copy and paste through the system clipboard. Where that is not available (e.g. on the web
+/// or a headless machine), the text is only kept within the game.
+#[derive(Default, Resource)]
+pub struct Clipboard {
+    /// Created on first use, since it connects to the display server on some platforms
+    #[cfg(not(target_arch = "wasm32"))]
+    system: Option<std::sync::Mutex<arboard::Clipboard>>,
+    text: Option<String>,
+}
+
+impl Debug for Clipboard {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "clipboard")
+    }
+}
+
+impl Clipboard {
+    #[cfg(not(target_arch = "wasm32"))]
+    fn system(&mut self) -> Option<&mut arboard::Clipboard> {
+        if self.system.is_none() {
+            self.system = arboard::Clipboard::new().ok().map(std::sync::Mutex::new);
+        }
+        self.system
+            .as_mut()
+            .and_then(|system| system.get_mut().ok())
+    }
+
+    pub fn get(&mut self) -> Option<String> {
+        #[cfg(not(target_arch = "wasm32"))]
+        if let Some(system) = self.system() {
+            return system.get_text().ok();
+        }
+        self.text.clone()
+    }
+
+    pub fn set(&mut self, text: &str) {
+        #[cfg(not(target_arch = "wasm32"))]
+        if let Some(system) = self.system()
+            && system.set_text(text).is_ok()
+        {
+            return;
+        }
+        self.text = Some(text.to_string());
+    }
+}
+
+type IsLoadedFn = Box<dyn Fn(&ResourceStorage) -> bool + Send + Sync>;
+
+/// Assets that are requested through [`GameAssets`], but are not loaded yet.
+#[derive(Default, Resource)]
+pub struct LoadingAssets {
+    pending: Vec<(AssetName, IsLoadedFn)>,
+}
+
+impl Debug for LoadingAssets {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "loading assets ({})", self.pending.len())
+    }
+}
+
+impl LoadingAssets {
+    #[must_use]
+    pub fn is_empty(&self) -> bool {
+        self.pending.is_empty()
+    }
+
+    /// Removes and returns the names of the assets that have been loaded since the last call.
+    pub fn take_loaded(storage: &mut ResourceStorage) -> Vec<AssetName> {
+        let Some(loading) = storage.get_mut::<Self>() else {
+            return Vec::new();
+        };
+        let pending = std::mem::take(&mut loading.pending);
+
+        let (loaded, still_pending): (Vec<_>, Vec<_>) = pending
+            .into_iter()
+            .partition(|(_, is_loaded)| is_loaded(storage));
+
+        if let Some(loading) = storage.get_mut::<Self>() {
+            loading.pending = still_pending;
+        }
+
+        loaded.into_iter().map(|(name, _)| name).collect()
+    }
+}
+
+/// Conveniences for [`ResourceStorage`], which already has `insert`, `remove` and `contains`.
+/// Kept private, since it belongs in `limnus-resource`.
+trait ResourceStorageExt {
+    /// Returns the resource, inserting the one returned by `create` first if it is absent.
+    fn get_or_insert_with<R: Resource>(&mut self, create: impl FnOnce() -> R) -> &mut R;
+}
+
+impl ResourceStorageExt for ResourceStorage {
+    fn get_or_insert_with<R: Resource>(&mut self, create: impl FnOnce() -> R) -> &mut R {
+        if !self.contains::<R>() {
+            self.insert(create());
+        }
+        self.fetch_mut::<R>()
+    }
 }
 
 pub struct GameAssets<'a> {
     now: Millis,
+    delta: MillisDuration,
     resource_storage: &'a mut ResourceStorage,
 }
 
@@ -82,9 +450,44 @@ impl<'a> GameAssets<'a> {
     pub const fn new(resource_storage: &'a mut ResourceStorage, now: Millis) -> Self {
         Self {
             now,
+            delta: MillisDuration::from_millis(0),
             resource_storage,
         }
     }
+
+    #[must_use]
+    pub const fn with_delta(mut self, delta: MillisDuration) -> Self {
+        self.delta = delta;
+        self
+    }
+
+    fn load<T: Asset>(&mut self, name: impl Into<AssetName>) -> Id<T> {
+        let name = name.into();
+        let id = self
+            .resource_storage
+            .get_mut::<AssetRegistry>()
+            .expect("should exist registry")
+            .load::<T>(name);
+
+        if let Some(loading) = self.resource_storage.get_mut::<LoadingAssets>() {
+            let pending_id = id.clone();
+            loading.pending.push((
+                name,
+                Box::new(move |storage: &ResourceStorage| {
+                    storage
+                        .get::<limnus_assets::Assets<T>>()
+                        .is_some_and(|assets| assets.contains(&pending_id))
+                }),
+            ));
+        }
+
+        #[cfg(all(feature = "hot-reload", not(target_arch = "wasm32")))]
+        if let Some(hot_reload) = self.resource_storage.get_mut::<HotReload>() {
+            hot_reload.watch((&id).into(), name);
+        }
+
+        id
+    }
 }
 
 impl Assets for GameAssets<'_> {
@@ -92,31 +495,144 @@ impl Assets for GameAssets<'_> {
         self.now
     }
 
+    fn delta(&self) -> MillisDuration {
+        self.delta
+    }
+
     fn texture_png(&mut self, name: impl Into<AssetName>) -> TextureRef {
-        let asset_loader = self
+        let texture_id = self.load::<Texture>(name.into().with_extension("png"));
+
+        TextureRef::from(texture_id)
+    }
+
+    fn material_png(&mut self, name: impl Into<AssetName>) -> MaterialRef {
+        let texture_ref = self.load::<Texture>(name.into().with_extension("png"));
+
+        let material = Material {
+            base: MaterialBase {
+                //pipeline: self.renderer().normal_sprite_pipeline.clone(),
+            },
+            kind: MaterialKind::NormalSprite {
+                primary_texture: texture_ref,
+                blend_mode: BlendMode::Alpha,
+            },
+        };
+
+        Arc::new(material)
+    }
+
+    fn material_png_ex(
+        &mut self,
+        name: impl Into<AssetName>,
+        options: TextureOptions,
+    ) -> MaterialRef {
+        let name: AssetName = name.into().with_extension("png").into();
+        self.resource_storage
+            .get_or_insert_with(TextureOptionsByName::default)
+            .insert(name.value(), options);
+        let texture_ref = self.load::<Texture>(name);
+
+        let blend_mode = if options.premultiply_alpha {
+            BlendMode::PremultipliedAlpha
+        } else {
+            BlendMode::Alpha
+        };
+        Arc::new(Material {
+            base: MaterialBase {},
+            kind: MaterialKind::NormalSprite {
+                primary_texture: texture_ref,
+                blend_mode,
+            },
+        })
+    }
+
+    fn render_target_material(&mut self, name: impl Into<AssetName>, size: UVec2) -> MaterialRef {
+        let texture_ref = self
             .resource_storage
             .get_mut::<AssetRegistry>()
-            .expect("should exist registry");
+            .expect("should exist registry")
+            .allocate_id::<Texture>(name);
+
+        let texture = self
+            .resource_storage
+            .get_mut::<Render>()
+            .expect("should exist render")
+            .create_render_target(&texture_ref, size);
 
-        let texture_id = asset_loader.load::<Texture>(name.into().with_extension("png"));
+        self.resource_storage
+            .get_mut::<limnus_assets::Assets<Texture>>()
+            .expect("texture assets should exist")
+            .set(&texture_ref, texture);
 
-        TextureRef::from(texture_id)
+        let material = Material {
+            base: MaterialBase {},
+            kind: MaterialKind::NormalSprite {
+                primary_texture: texture_ref,
+                blend_mode: BlendMode::Alpha,
+            },
+        };
+
+        Arc::new(material)
     }
 
-    fn material_png(&mut self, name: impl Into<AssetName>) -> MaterialRef {
-        let asset_loader = self
+    fn texture_from_rgba(
+        &mut self,
+        name: impl Into<AssetName>,
+        rgba: &[u8],
+        size: UVec2,
+    ) -> TextureRef {
+        let name = name.into();
+        let texture_ref = self
             .resource_storage
             .get_mut::<AssetRegistry>()
-            .expect("should exist registry");
+            .expect("should exist registry")
+            .allocate_id::<Texture>(name);
+
+        let texture = self
+            .resource_storage
+            .get::<Render>()
+            .expect("should exist render")
+            .texture_from_rgba(rgba, size, name.value());
 
-        let texture_ref = asset_loader.load::<Texture>(name.into().with_extension("png"));
+        self.resource_storage
+            .get_mut::<limnus_assets::Assets<Texture>>()
+            .expect("texture assets should exist")
+            .set(&texture_ref, texture);
+
+        texture_ref
+    }
+
+    fn material_from_rgba(
+        &mut self,
+        name: impl Into<AssetName>,
+        rgba: &[u8],
+        size: UVec2,
+    ) -> MaterialRef {
+        let texture_ref = self.texture_from_rgba(name, rgba, size);
 
         let material = Material {
-            base: MaterialBase {
-                //pipeline: self.renderer().normal_sprite_pipeline.clone(),
+            base: MaterialBase {},
+            kind: MaterialKind::NormalSprite {
+                primary_texture: texture_ref,
+                blend_mode: BlendMode::Alpha,
             },
+        };
+
+        Arc::new(material)
+    }
+
+    fn material_png_blend(
+        &mut self,
+        name: impl Into<AssetName>,
+        blend_mode: BlendMode,
+    ) -> MaterialRef {
+        let texture_ref = self.load::<Texture>(name.into().with_extension("png"));
+
+        let material = Material {
+            base: MaterialBase {},
             kind: MaterialKind::NormalSprite {
                 primary_texture: texture_ref,
+                blend_mode,
             },
         };
 
@@ -128,12 +644,8 @@ impl Assets for GameAssets<'_> {
         name: impl Into<AssetName>,
         mask: impl Into<AssetName>,
     ) -> MaterialRef {
-        let asset_loader = self
-            .resource_storage
-            .get_mut::<AssetRegistry>()
-            .expect("should exist registry");
-        let diffuse_texture_id = asset_loader.load::<Texture>(name.into().with_extension("png"));
-        let alpha_mask_texture_id = asset_loader.load::<Texture>(mask.into().with_extension("png"));
+        let diffuse_texture_id = self.load::<Texture>(name.into().with_extension("png"));
+        let alpha_mask_texture_id = self.load::<Texture>(mask.into().with_extension("png"));
         let material = Material {
             base: MaterialBase {},
             kind: MaterialKind::AlphaMasker {
@@ -146,12 +658,7 @@ impl Assets for GameAssets<'_> {
     }
 
     fn light_material_png(&mut self, name: impl Into<AssetName>) -> MaterialRef {
-        let asset_loader = self
-            .resource_storage
-            .get_mut::<AssetRegistry>()
-            .expect("should exist registry");
-
-        let texture_ref = asset_loader.load::<Texture>(name.into().with_extension("png"));
+        let texture_ref = self.load::<Texture>(name.into().with_extension("png"));
 
         let material = Material {
             base: MaterialBase {
@@ -173,7 +680,13 @@ impl Assets for GameAssets<'_> {
     ) -> FixedAtlas {
         let material_ref = self.material_png(name);
 
-        FixedAtlas::new(grid_size, texture_size, material_ref)
+        FixedAtlas::new(
+            grid_size,
+            texture_size,
+            UVec2::new(0, 0),
+            UVec2::new(0, 0),
+            material_ref,
+        )
     }
 
     fn nine_slice_material_png(
@@ -186,17 +699,15 @@ impl Assets for GameAssets<'_> {
         NineSliceAndMaterial {
             slices,
             material_ref,
+            mode: NineSliceMode::default(),
+            draw_center: true,
         }
     }
 
     fn bm_font(&mut self, name: impl Into<AssetName>) -> FontAndMaterial {
         let asset_name = name.into();
-        let asset_loader = self
-            .resource_storage
-            .get_mut::<AssetRegistry>()
-            .expect("should exist registry");
-        let font_ref = asset_loader.load::<Font>(asset_name.clone().with_extension("fnt"));
-        let texture_id = asset_loader.load::<Texture>(asset_name.clone().with_extension("png"));
+        let font_ref = self.load::<Font>(asset_name.clone().with_extension("fnt"));
+        let texture_id = self.load::<Texture>(asset_name.clone().with_extension("png"));
 
         let material = Material {
             base: MaterialBase {
@@ -204,6 +715,7 @@ impl Assets for GameAssets<'_> {
             },
             kind: MaterialKind::NormalSprite {
                 primary_texture: texture_id,
+                blend_mode: BlendMode::Alpha,
             },
         };
 
@@ -215,12 +727,8 @@ impl Assets for GameAssets<'_> {
 
     fn bm_font_txt(&mut self, name: impl Into<AssetName>) -> FontAndMaterial {
         let asset_name = name.into();
-        let asset_loader = self
-            .resource_storage
-            .get_mut::<AssetRegistry>()
-            .expect("should exist registry");
-        let font_ref = asset_loader.load::<Font>(asset_name.clone().with_extension("txt.fnt"));
-        let texture_id = asset_loader.load::<Texture>(asset_name.clone().with_extension("png"));
+        let font_ref = self.load::<Font>(asset_name.clone().with_extension("txt.fnt"));
+        let texture_id = self.load::<Texture>(asset_name.clone().with_extension("png"));
 
         let material = Material {
             base: MaterialBase {
@@ -228,6 +736,39 @@ impl Assets for GameAssets<'_> {
             },
             kind: MaterialKind::NormalSprite {
                 primary_texture: texture_id,
+                blend_mode: BlendMode::Alpha,
+            },
+        };
+
+        FontAndMaterial {
+            font_ref,
+            material_ref: Arc::new(material),
+        }
+    }
+
+    fn ttf_font(&mut self, name: impl Into<AssetName>, pixel_size: f32) -> FontAndMaterial {
+        let asset_name = name.into();
+        let font_ref = self.load::<Font>(asset_name);
+        self.resource_storage
+            .get_mut::<FontRasterSizes>()
+            .expect("font raster sizes should exist")
+            .set((&font_ref).into(), pixel_size);
+
+        let texture_ref = self
+            .resource_storage
+            .get_mut::<AssetRegistry>()
+            .expect("should exist registry")
+            .allocate_id::<Texture>(asset_name.with_extension("atlas"));
+        self.resource_storage
+            .get_mut::<FontAtlases>()
+            .expect("font atlases should exist")
+            .push(font_ref.clone(), texture_ref.clone());
+
+        let material = Material {
+            base: MaterialBase {},
+            kind: MaterialKind::NormalSprite {
+                primary_texture: texture_ref,
+                blend_mode: BlendMode::Alpha,
             },
         };
 
@@ -257,10 +798,74 @@ impl Assets for GameAssets<'_> {
     }
 
     fn audio_sample_wav(&mut self, name: impl Into<AssetName>) -> StereoSampleRef {
-        let asset_loader = self
-            .resource_storage
-            .get_mut::<AssetRegistry>()
-            .expect("should exist registry");
-        asset_loader.load::<StereoSample>(name.into().with_extension("wav"))
+        self.load::<StereoSample>(name.into().with_extension("wav"))
+    }
+
+    fn keys(&self) -> &Keys {
+        self.resource_storage
+            .get::<Keys>()
+            .expect("keys resource should exist")
+    }
+
+    fn mouse(&self) -> &Mouse {
+        self.resource_storage
+            .get::<Mouse>()
+            .expect("mouse resource should exist")
+    }
+
+    fn supports_rumble(&self) -> bool {
+        self.resource_storage
+            .get::<GamepadRumble>()
+            .is_some_and(|rumble| rumble.supported)
+    }
+
+    fn gpu_info(&self) -> Option<&GpuInfo> {
+        self.resource_storage.get::<GpuInfo>()
+    }
+
+    fn is_loaded<T: Asset>(&self, id: &Id<T>) -> bool {
+        self.resource_storage
+            .get::<limnus_assets::Assets<T>>()
+            .is_some_and(|assets| assets.contains(id))
+    }
+
+    fn is_material_loaded(&self, material: &MaterialRef) -> bool {
+        self.resource_storage
+            .get::<limnus_assets::Assets<Texture>>()
+            .is_some_and(|textures| material.is_complete(textures))
+    }
+
+    fn is_font_loaded(&self, font_and_material: &FontAndMaterial) -> bool {
+        self.is_loaded(&font_and_material.font_ref)
+            && self.is_material_loaded(&font_and_material.material_ref)
+    }
+
+    fn set_rumble(
+        &mut self,
+        gamepad_id: GamePadId,
+        strong: f32,
+        weak: f32,
+        duration: MillisDuration,
+    ) {
+        if let Some(rumble) = self.resource_storage.get_mut::<GamepadRumble>() {
+            rumble.push(RumbleRequest {
+                gamepad_id,
+                strong: strong.clamp(0.0, 1.0),
+                weak: weak.clamp(0.0, 1.0),
+                duration,
+            });
+        }
+    }
+
+    fn clipboard_get(&mut self) -> Option<String> {
+        self.resource_storage
+            .get_or_insert_with(Clipboard::default)
+            .get()
+    }
+
+    fn clipboard_set(&mut self, text: &str) {
+        self.resource_storage
+            .get_or_insert_with(Clipboard::default)
+            .set(text);
     }
 }
@@ -4,36 +4,302 @@
  */
 use limnus_assets::Assets;
 use limnus_audio_mixer::{AudioMixer, StereoSample, StereoSampleRef};
+use limnus_local_resource::prelude::LocalResource;
+use monotonic_time_rs::{Millis, MillisDuration};
+use oddio::{Cycle, FramesSignal, Mixed, MixerControl, Signal};
+use std::collections::HashMap;
+use std::fmt::{Debug, Formatter};
+use std::sync::Arc;
+use std::sync::atomic::{AtomicU32, Ordering};
 use tracing::debug;
 
 pub type SoundHandle = u16;
+pub type MusicHandle = SoundHandle;
+
+/// Mixer category of a sound. Each bus has its own volume, which is multiplied with the
+/// [`Bus::Master`] volume.
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum Bus {
+    Master,
+    Music,
+    Sfx,
+}
+
+impl Bus {
+    const fn index(self) -> usize {
+        match self {
+            Self::Master => 0,
+            Self::Music => 1,
+            Self::Sfx => 2,
+        }
+    }
+}
 
 pub trait Audio {
-    fn play(&mut self, audio: &StereoSampleRef) -> SoundHandle;
+    /// Plays the sample once on `bus`. `volume` is a linear gain, where `1.0` is unchanged,
+    /// and `pan` goes from `-1.0` (left) to `1.0` (right).
+    fn play(&mut self, audio: &StereoSampleRef, bus: Bus, volume: f32, pan: f32) -> SoundHandle;
+
+    /// Plays the sample over and over on [`Bus::Music`], at full volume, until it is stopped.
+    fn play_looping(&mut self, audio: &StereoSampleRef) -> MusicHandle;
+
+    /// Sets the linear volume of a bus, affecting the sounds that are already playing as well.
+    fn set_bus_volume(&mut self, bus: Bus, volume: f32);
+
+    fn bus_volume(&self, bus: Bus) -> f32;
+
+    /// Stops a sound or music directly. Does nothing if it has already finished.
+    fn stop(&mut self, handle: SoundHandle);
+
+    /// Changes the volume linearly over `duration`, evaluated each audio tick.
+    /// A fade to zero stops the sound when it completes, which is what a crossfade needs.
+    fn fade_to(&mut self, handle: SoundHandle, target_volume: f32, duration: MillisDuration);
+}
+
+#[derive(Debug)]
+struct Fade {
+    from: f32,
+    to: f32,
+    started_at: Millis,
+    duration: MillisDuration,
+}
+
+/// Left and right gain, shared between a [`Voices`] entry and the signal on the audio thread.
+#[derive(Debug, Default)]
+struct StereoGain {
+    left: AtomicU32,
+    right: AtomicU32,
+}
+
+impl StereoGain {
+    fn set(&self, volume: f32, pan: f32) {
+        // Balance law, so a centered sound plays at the volume of the sample itself
+        let pan = pan.clamp(-1.0, 1.0);
+        let volume = volume.max(0.0);
+        self.left
+            .store((volume * (1.0 - pan).min(1.0)).to_bits(), Ordering::Relaxed);
+        self.right
+            .store((volume * (1.0 + pan).min(1.0)).to_bits(), Ordering::Relaxed);
+    }
+
+    fn get(&self) -> (f32, f32) {
+        (
+            f32::from_bits(self.left.load(Ordering::Relaxed)),
+            f32::from_bits(self.right.load(Ordering::Relaxed)),
+        )
+    }
+}
+
+struct Voice<S> {
+    signal: S,
+    gain: Arc<StereoGain>,
+}
+
+impl<S: Signal<Frame = [f32; 2]>> Signal for Voice<S> {
+    type Frame = [f32; 2];
+
+    fn sample(&mut self, interval: f32, out: &mut [Self::Frame]) {
+        self.signal.sample(interval, out);
+        let (left, right) = self.gain.get();
+        for frame in out {
+            frame[0] *= left;
+            frame[1] *= right;
+        }
+    }
+
+    fn is_finished(&self) -> bool {
+        self.signal.is_finished()
+    }
+}
+
+struct PlayingSound {
+    mixed: Mixed,
+    gain: Arc<StereoGain>,
+    bus: Bus,
+    volume: f32,
+    pan: f32,
+    fade: Option<Fade>,
+}
+
+/// The sounds started through [`GameAudio`].
+///
+/// Takes over the low level mixer of the [`AudioMixer`], since that one can only play samples
+/// without any way to control them afterward.
+#[derive(LocalResource)]
+pub struct Voices {
+    control: MixerControl<[f32; 2]>,
+    playing: HashMap<SoundHandle, PlayingSound>,
+    next_handle: SoundHandle,
+    now: Millis,
+    bus_volumes: [f32; 3],
+}
+
+impl Debug for Voices {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "Voices ({})", self.playing.len())
+    }
+}
+
+impl Voices {
+    /// # Panics
+    /// If the mixer lock is poisoned.
+    #[must_use]
+    pub fn new(mixer: &AudioMixer) -> Self {
+        let (control, low_level_mixer) = oddio::Mixer::<[f32; 2]>::new();
+        *mixer.mixer.lock().unwrap() = low_level_mixer;
+        Self {
+            control,
+            playing: HashMap::new(),
+            next_handle: 0,
+            now: Millis::new(0),
+            bus_volumes: [1.0; 3],
+        }
+    }
+
+    fn bus_gain(bus_volumes: &[f32; 3], bus: Bus) -> f32 {
+        match bus {
+            Bus::Master => bus_volumes[Bus::Master.index()],
+            _ => bus_volumes[bus.index()] * bus_volumes[Bus::Master.index()],
+        }
+    }
+
+    fn set_bus_volume(&mut self, bus: Bus, volume: f32) {
+        self.bus_volumes[bus.index()] = volume.max(0.0);
+        for sound in self.playing.values() {
+            let bus_gain = Self::bus_gain(&self.bus_volumes, sound.bus);
+            sound.gain.set(sound.volume * bus_gain, sound.pan);
+        }
+    }
+
+    /// Advances the fades, should be called from the audio tick before any [`GameAudio`] calls.
+    pub fn update(&mut self, now: Millis) {
+        self.now = now;
+        for sound in self.playing.values_mut() {
+            let Some(fade) = &sound.fade else {
+                continue;
+            };
+            let elapsed = now.duration_since_ms(fade.started_at);
+            let done = elapsed >= fade.duration;
+            sound.volume = if done {
+                fade.to
+            } else {
+                let t = elapsed.as_secs() / fade.duration.as_secs();
+                fade.from + (fade.to - fade.from) * t
+            };
+            let bus_gain = Self::bus_gain(&self.bus_volumes, sound.bus);
+            sound.gain.set(sound.volume * bus_gain, sound.pan);
+            if done {
+                if sound.volume <= 0.0 {
+                    sound.mixed.stop();
+                }
+                sound.fade = None;
+            }
+        }
+        self.playing.retain(|_, sound| !sound.mixed.is_stopped());
+    }
+
+    fn allocate_handle(&mut self) -> SoundHandle {
+        self.playing.retain(|_, sound| !sound.mixed.is_stopped());
+        while self.playing.contains_key(&self.next_handle) {
+            self.next_handle = self.next_handle.wrapping_add(1);
+        }
+        let handle = self.next_handle;
+        self.next_handle = self.next_handle.wrapping_add(1);
+        handle
+    }
+
+    fn play<S>(&mut self, signal: S, bus: Bus, volume: f32, pan: f32) -> SoundHandle
+    where
+        S: Signal<Frame = [f32; 2]> + Send + 'static,
+    {
+        let gain = Arc::new(StereoGain::default());
+        gain.set(volume * Self::bus_gain(&self.bus_volumes, bus), pan);
+        let mixed = self.control.play(Voice {
+            signal,
+            gain: gain.clone(),
+        });
+        let handle = self.allocate_handle();
+        self.playing.insert(
+            handle,
+            PlayingSound {
+                mixed,
+                gain,
+                bus,
+                volume,
+                pan,
+                fade: None,
+            },
+        );
+        handle
+    }
+
+    fn stop(&mut self, handle: SoundHandle) {
+        if let Some(mut sound) = self.playing.remove(&handle) {
+            sound.mixed.stop();
+        }
+    }
+
+    fn fade_to(&mut self, handle: SoundHandle, target_volume: f32, duration: MillisDuration) {
+        if let Some(sound) = self.playing.get_mut(&handle) {
+            sound.fade = Some(Fade {
+                from: sound.volume,
+                to: target_volume.max(0.0),
+                started_at: self.now,
+                duration,
+            });
+        }
+    }
 }
 
 // We will only borrow these resources for a single function call
 pub struct GameAudio<'a> {
-    pub mixer: &'a mut AudioMixer,
+    pub voices: &'a mut Voices,
     pub stereo_samples: &'a Assets<StereoSample>,
 }
 
 impl<'a> GameAudio<'a> {
-    pub const fn new(mixer: &'a mut AudioMixer, stereo_samples: &'a Assets<StereoSample>) -> Self {
+    pub const fn new(voices: &'a mut Voices, stereo_samples: &'a Assets<StereoSample>) -> Self {
         Self {
-            mixer,
+            voices,
             stereo_samples,
         }
     }
 }
 
 impl Audio for GameAudio<'_> {
-    fn play(&mut self, sample_id: &StereoSampleRef) -> SoundHandle {
-        debug!(sample_id=%sample_id, "playing sample");
+    fn play(
+        &mut self,
+        sample_id: &StereoSampleRef,
+        bus: Bus,
+        volume: f32,
+        pan: f32,
+    ) -> SoundHandle {
+        debug!(sample_id=%sample_id, ?bus, volume, pan, "playing sample");
         let stereo_sample = self.stereo_samples.fetch(sample_id);
-        self.mixer.play(stereo_sample);
+        let (_, signal) = FramesSignal::new(stereo_sample.frames().clone(), 0.0);
+        self.voices.play(signal, bus, volume, pan)
+    }
+
+    fn play_looping(&mut self, sample_id: &StereoSampleRef) -> MusicHandle {
+        debug!(sample_id=%sample_id, "playing looping sample");
+        let stereo_sample = self.stereo_samples.fetch(sample_id);
+        let signal = Cycle::new(stereo_sample.frames().clone());
+        self.voices.play(signal, Bus::Music, 1.0, 0.0)
+    }
+
+    fn set_bus_volume(&mut self, bus: Bus, volume: f32) {
+        self.voices.set_bus_volume(bus, volume);
+    }
+
+    fn bus_volume(&self, bus: Bus) -> f32 {
+        self.voices.bus_volumes[bus.index()]
+    }
+
+    fn stop(&mut self, handle: SoundHandle) {
+        self.voices.stop(handle);
+    }
 
-        // TODO: fix this
-        53
+    fn fade_to(&mut self, handle: SoundHandle, target_volume: f32, duration: MillisDuration) {
+        self.voices.fade_to(handle, target_volume, duration);
     }
 }
@@ -6,10 +6,11 @@ extern crate core;
 
 pub mod prelude;
 
-use int_math::{URect, UVec2, Vec2};
+use int_math::{Rect, UVec2, Vec2};
 
 use fixed32::Fp;
 use limnus_app::prelude::{App, AppReturnValue, ApplicationExit, Plugin};
+use limnus_assets::prelude::AssetName;
 use limnus_audio_mixer::{AudioMixer, StereoSample};
 use limnus_basic_input::InputMessage;
 use limnus_basic_input::prelude::{
@@ -18,23 +19,28 @@ use limnus_basic_input::prelude::{
 use limnus_default_stages::{FixedUpdate, RenderUpdate, Update};
 use limnus_gamepad::{Axis, Button, GamePadId, Gamepad, GamepadMessage, Gamepads};
 use limnus_local_resource::prelude::LocalResource;
-use limnus_message::MessagesIterator;
+use limnus_message::{Message, MessagesIterator};
 use limnus_resource::ResourceStorage;
 use limnus_resource::prelude::Resource;
-use limnus_screen::WindowMessage;
+use limnus_screen::{ScreenMode, Window, WindowMessage};
 use limnus_system_params::{LoReM, Msg, Re, ReAll, ReM};
-use mireforge_game_assets::{Assets, GameAssets};
-use mireforge_game_audio::{Audio, GameAudio};
+use mireforge_game_assets::{
+    Assets, GameAssets, GamepadRumble, Keys, LoadingAssets, Mouse, wheel_steps,
+};
+use mireforge_game_audio::{Audio, GameAudio, Voices};
 use mireforge_render_wgpu::prelude::{Gfx, Render};
-use monotonic_time_rs::{InstantMonotonicClock, Millis, MonotonicClock};
+use monotonic_time_rs::{InstantMonotonicClock, Millis, MillisDuration, MonotonicClock};
+use std::any::type_name;
 use std::cmp::{max, min};
 use std::fmt::{Debug, Formatter};
 use std::marker::PhantomData;
-use tracing::debug;
+use tracing::{debug, error, warn};
 
 pub trait Application: Sized + 'static {
     fn new(assets: &mut impl Assets) -> Self;
     fn tick(&mut self, assets: &mut impl Assets);
+    /// Called once per frame. `Gfx::now` and `Gfx::interpolation_alpha` tell where the frame is
+    /// between two fixed `tick`s.
     fn render(&mut self, gfx: &mut impl Gfx);
     fn audio(&mut self, _audio: &mut impl Audio) {}
 
@@ -42,6 +48,12 @@ pub trait Application: Sized + 'static {
         false
     }
 
+    /// Polled every tick. `Some(true)` switches the window to borderless fullscreen and
+    /// `Some(false)` back to windowed, e.g. for an F11 toggle. `None` leaves it as it is.
+    fn wants_fullscreen(&self) -> Option<bool> {
+        None
+    }
+
     fn wants_cursor_visible(&self) -> bool {
         true
     }
@@ -54,14 +66,37 @@ pub trait Application: Sized + 'static {
 
     fn cursor_moved(&mut self, _position: UVec2) {}
 
+    /// Same as `cursor_moved`, but the position is converted to world coordinates
+    /// using the current camera.
+    fn cursor_moved_world(&mut self, _position: Vec2) {}
+
+    /// `WindowMessage::Touch` does not carry a finger id, so concurrent touches
+    /// are reported through the same call.
     fn touch(&mut self, _position: UVec2, _touch_phase: &TouchPhase) {}
 
     fn mouse_input(&mut self, _state: ButtonState, _button: MouseButton) {}
 
     fn mouse_wheel(&mut self, _delta_y: i16) {}
 
+    /// Both axes of a wheel or touchpad scroll, in lines or pixels as reported by the platform.
+    /// The default forwards line deltas to `mouse_wheel`, see [`wheel_steps`].
+    fn mouse_scroll(&mut self, delta: MouseScrollDelta) {
+        if let Some(delta_y) = wheel_steps(delta) {
+            self.mouse_wheel(delta_y);
+        }
+    }
+
+    /// Relative mouse motion, for mouse-look. Not called yet, since `limnus-window-runner` drops
+    /// the motion events, see TODO.md. The delta is meant to be unscaled, the game applies its
+    /// own sensitivity.
     fn mouse_motion(&mut self, _delta: Vec2) {}
 
+    /// Called once for each asset that has finished loading, before the next `tick`.
+    fn asset_loaded(&mut self, _name: AssetName) {}
+
+    /// Called when a gamepad is connected, before it is activated by its first button press.
+    /// Only activated gamepads are reported to `gamepad_disconnected`.
+    fn gamepad_connected(&mut self, _gamepad_id: GamePadId, _name: &str) {}
     fn gamepad_activated(&mut self, _gamepad_id: GamePadId, _name: String) {}
     fn gamepad_button_changed(&mut self, _gamepad: &Gamepad, _button: Button, _value: Fp) {}
     fn gamepad_axis_changed(&mut self, _gamepad: &Gamepad, _axis: Axis, _value: Fp) {}
@@ -72,15 +107,44 @@ pub trait Application: Sized + 'static {
     }
 }
 
+/// Implemented by an [`Application`] for each custom message type it wants to receive.
+/// Add a [`GameMessagePlugin`] for the message type to have it called.
+pub trait ApplicationMessage<M: Message> {
+    fn message(&mut self, message: &M);
+}
+
 #[derive(Debug, Resource)]
 pub struct GameSettings {
     pub virtual_size: UVec2,
+    /// How much time each `Application::tick` simulates. The logic is ticked zero or more
+    /// times per frame, so the simulation speed does not depend on the frame rate.
+    pub fixed_step: MillisDuration,
+}
+
+impl GameSettings {
+    /// Roughly 60 ticks per second
+    pub const DEFAULT_FIXED_STEP: MillisDuration = MillisDuration::from_millis(16);
+
+    #[must_use]
+    pub const fn new(virtual_size: UVec2) -> Self {
+        Self {
+            virtual_size,
+            fixed_step: Self::DEFAULT_FIXED_STEP,
+        }
+    }
 }
 
+/// Limits how many ticks that are run in one frame, so a long stall (e.g. a dragged window)
+/// does not make the game spend several frames catching up.
+const MAXIMUM_TICKS_PER_FRAME: u64 = 8;
+
 #[derive(LocalResource)]
 pub struct Game<G: Application> {
     game: G,
     clock: InstantMonotonicClock,
+    last_tick_at: Option<Millis>,
+    accumulated: MillisDuration,
+    fixed_step: MillisDuration,
 }
 
 impl<G: Application> Debug for Game<G> {
@@ -96,7 +160,13 @@ impl<G: Application> Game<G> {
         let mut assets = GameAssets::new(all_resources, clock.now());
         let game = G::new(&mut assets);
 
-        Self { game, clock }
+        Self {
+            game,
+            clock,
+            last_tick_at: None,
+            accumulated: MillisDuration::from_millis(0),
+            fixed_step: GameSettings::DEFAULT_FIXED_STEP,
+        }
     }
 
     pub fn inputs(&mut self, iter: MessagesIterator<InputMessage>) {
@@ -109,10 +179,7 @@ impl<G: Application> Game<G> {
                     self.game.mouse_input(*button_state, *button);
                 }
                 InputMessage::MouseWheel(scroll_delta, _touch_phase) => {
-                    if let MouseScrollDelta::LineDelta(delta) = scroll_delta {
-                        let game_scroll_y = (f32::from(-delta.y) * 120.0) as i16;
-                        self.game.mouse_wheel(game_scroll_y);
-                    }
+                    self.game.mouse_scroll(*scroll_delta);
                 }
             }
         }
@@ -121,7 +188,7 @@ impl<G: Application> Game<G> {
     #[must_use]
     pub fn virtual_position_from_physical(
         physical_position: UVec2,
-        viewport: URect,
+        viewport: Rect,
         virtual_surface_size: UVec2,
     ) -> UVec2 {
         let relative_x = max(
@@ -153,22 +220,24 @@ impl<G: Application> Game<G> {
         UVec2::new(virtual_position_x as u16, virtual_position_y as u16)
     }
 
-    pub fn cursor_moved(
-        &mut self,
-        physical_position: UVec2,
-        viewport: URect,
-        virtual_surface_size: UVec2,
-    ) {
-        let virtual_position =
-            Self::virtual_position_from_physical(physical_position, viewport, virtual_surface_size);
+    /// Notifies the game about the cursor and returns the position in virtual surface coordinates.
+    pub fn cursor_moved(&mut self, physical_position: UVec2, wgpu_render: &Render) -> UVec2 {
+        let virtual_position = Self::virtual_position_from_physical(
+            physical_position,
+            wgpu_render.viewport(),
+            wgpu_render.virtual_surface_size_with_scaling(),
+        );
         self.game.cursor_moved(virtual_position);
+        self.game
+            .cursor_moved_world(wgpu_render.virtual_to_world(virtual_position));
+        virtual_position
     }
 
     pub fn touch(
         &mut self,
         physical_position: UVec2,
         touch_phase: &TouchPhase,
-        viewport: URect,
+        viewport: Rect,
         virtual_surface_size: UVec2,
     ) {
         let virtual_position =
@@ -176,14 +245,18 @@ impl<G: Application> Game<G> {
         self.game.touch(virtual_position, touch_phase);
     }
 
-    pub fn mouse_move(&mut self, iter: MessagesIterator<WindowMessage>, wgpu_render: &Render) {
+    pub fn mouse_move(
+        &mut self,
+        iter: MessagesIterator<WindowMessage>,
+        wgpu_render: &Render,
+        mouse: &mut Mouse,
+    ) {
         for message in iter {
             match message {
-                WindowMessage::CursorMoved(position) => self.cursor_moved(
-                    *position,
-                    wgpu_render.viewport(),
-                    wgpu_render.virtual_surface_size_with_scaling(),
-                ),
+                WindowMessage::CursorMoved(position) => {
+                    let virtual_position = self.cursor_moved(*position, wgpu_render);
+                    mouse.set_virtual_position(virtual_position);
+                }
                 WindowMessage::Touch(position, touch_phase) => self.touch(
                     *position,
                     touch_phase,
@@ -196,15 +269,56 @@ impl<G: Application> Game<G> {
         }
     }
 
-    pub fn tick(&mut self, storage: &mut ResourceStorage, now: Millis) {
+    /// Ticks the game as many `fixed_step`s as have elapsed since the previous call.
+    /// The first call always ticks once.
+    pub fn fixed_tick(
+        &mut self,
+        storage: &mut ResourceStorage,
+        now: Millis,
+        fixed_step: MillisDuration,
+    ) {
+        let elapsed = self.last_tick_at.map_or(fixed_step, |last_tick_at| {
+            now.checked_duration_since_ms(last_tick_at)
+                .unwrap_or(MillisDuration::from_millis(0))
+        });
+        self.last_tick_at = Some(now);
+        self.fixed_step = fixed_step;
+
+        let step_ms = fixed_step.as_millis().max(1);
+        let accumulated_ms = (self.accumulated.as_millis() + elapsed.as_millis())
+            .min(step_ms * MAXIMUM_TICKS_PER_FRAME);
+
+        for _ in 0..accumulated_ms / step_ms {
+            self.tick(storage, now, fixed_step);
+        }
+        self.accumulated = MillisDuration::from_millis(accumulated_ms % step_ms);
+    }
+
+    pub fn tick(&mut self, storage: &mut ResourceStorage, now: Millis, delta: MillisDuration) {
+        for name in LoadingAssets::take_loaded(storage) {
+            self.game.asset_loaded(name);
+        }
+
         // This is a quick operation, we basically wrap storage
-        let mut assets = GameAssets::new(storage, now);
+        let mut assets = GameAssets::new(storage, now).with_delta(delta);
 
         self.game.tick(&mut assets);
+
+        if let Some(keys) = storage.get_mut::<Keys>() {
+            keys.clear_just_changed();
+        }
+    }
+
+    /// The part of a `fixed_step` that is left in the accumulator, from `0.0` to `1.0`.
+    #[must_use]
+    pub fn interpolation_alpha(&self) -> f32 {
+        let step_ms = self.fixed_step.as_millis().max(1);
+        self.accumulated.as_millis() as f32 / step_ms as f32
     }
 
     pub fn render(&mut self, wgpu_render: &mut Render, now: Millis) {
         wgpu_render.set_now(now);
+        wgpu_render.set_interpolation_alpha(self.interpolation_alpha());
         self.game.render(wgpu_render);
     }
 }
@@ -231,30 +345,51 @@ pub fn mouse_input_tick<G: Application>(
     mut internal_game: LoReM<Game<G>>,
     window_messages: Msg<WindowMessage>,
     wgpu_render: Re<Render>,
+    mut mouse: ReM<Mouse>,
 ) {
-    internal_game.mouse_move(window_messages.iter_previous(), &wgpu_render);
+    internal_game.mouse_move(window_messages.iter_previous(), &wgpu_render, &mut mouse);
 }
 
 pub fn keyboard_input_tick<G: Application>(
     mut internal_game: LoReM<Game<G>>,
     input_messages: Msg<InputMessage>,
+    mut keys: ReM<Keys>,
+    mut mouse: ReM<Mouse>,
 ) {
+    for message in input_messages.iter_previous() {
+        match message {
+            InputMessage::KeyboardInput(button_state, key_code) => match button_state {
+                ButtonState::Pressed => keys.press(*key_code),
+                ButtonState::Released => keys.release(*key_code),
+            },
+            InputMessage::MouseInput(button_state, button) => match button_state {
+                ButtonState::Pressed => mouse.press(*button),
+                ButtonState::Released => mouse.release(*button),
+            },
+            InputMessage::MouseWheel(..) => {}
+        }
+    }
     internal_game.inputs(input_messages.iter_previous());
 }
 
 pub fn audio_tick<G: Application>(
     mut internal_game: LoReM<Game<G>>,
     stereo_samples: Re<limnus_assets::Assets<StereoSample>>,
-    mut audio_mixer: LoReM<AudioMixer>,
+    mut voices: LoReM<Voices>,
 ) {
-    let mut game_audio = GameAudio::new(&mut audio_mixer, &stereo_samples);
+    voices.update(internal_game.clock.now());
+    let mut game_audio = GameAudio::new(&mut voices, &stereo_samples);
     internal_game.game.audio(&mut game_audio);
 }
 
 pub fn logic_tick<G: Application>(mut internal_game: LoReM<Game<G>>, mut all_resources: ReAll) {
     let now = internal_game.clock.now();
+    let fixed_step = all_resources.fetch::<GameSettings>().fixed_step;
 
-    internal_game.tick(&mut all_resources, now);
+    internal_game.fixed_tick(&mut all_resources, now, fixed_step);
+    if let Some(fullscreen) = internal_game.game.wants_fullscreen() {
+        set_fullscreen(all_resources.fetch_mut::<Window>(), fullscreen);
+    }
     if internal_game.game.wants_to_quit() {
         all_resources.insert(ApplicationExit {
             value: AppReturnValue::Value(0),
@@ -262,6 +397,17 @@ pub fn logic_tick<G: Application>(mut internal_game: LoReM<Game<G>>, mut all_res
     }
 }
 
+/// Switches `window` between windowed and borderless fullscreen. The window runner picks up
+/// the new mode on the next redraw and the surface follows the resize.
+pub fn set_fullscreen(window: &mut Window, fullscreen: bool) {
+    let is_fullscreen = matches!(window.mode, ScreenMode::WindowedFullscreen);
+    if fullscreen && !is_fullscreen {
+        window.mode = ScreenMode::WindowedFullscreen;
+    } else if !fullscreen && is_fullscreen {
+        window.mode = ScreenMode::Windowed;
+    }
+}
+
 pub fn render_tick<G: Application>(
     mut internal_game: LoReM<Game<G>>,
     mut wgpu_render: ReM<Render>,
@@ -278,7 +424,11 @@ pub fn gamepad_input_tick<G: Application>(
 ) {
     for gamepad_message in gamepad_messages.iter_current() {
         match gamepad_message {
-            GamepadMessage::Connected(_gamepad_id, _gamepad_name) => {}
+            GamepadMessage::Connected(gamepad_id, gamepad_name) => {
+                internal_game
+                    .game
+                    .gamepad_connected(*gamepad_id, gamepad_name);
+            }
             GamepadMessage::Disconnected(gamepad_id) => {
                 if let Some(gamepad) = gamepads.gamepad(*gamepad_id)
                     && gamepad.is_active
@@ -315,13 +465,127 @@ pub fn gamepad_input_tick<G: Application>(
     }
 }
 
+/// What to do when more messages than the forward limit were sent during an update.
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum MessageOverflow {
+    /// Only the newest messages, up to the limit, are forwarded.
+    DropOldest,
+    /// All messages are forwarded, but a warning is logged.
+    Warn,
+}
+
+/// Optional limit of how many messages of type `M` that are forwarded to the [`Application`]
+/// each update. The `Messages<M>` queue itself is not limited, other readers still see every
+/// message, and the `u16` message ids still wrap around if more than 65536 are sent.
+#[derive(Debug, Resource)]
+pub struct MessageForwardLimit<M: Message> {
+    pub limit: Option<usize>,
+    pub overflow: MessageOverflow,
+    phantom_data: PhantomData<M>,
+}
+
+/// Forwards the messages of type `M`, sent by any system during the previous update,
+/// to [`ApplicationMessage::message`].
+pub fn message_tick<G: Application + ApplicationMessage<M>, M: Message>(
+    mut internal_game: LoReM<Game<G>>,
+    messages: Msg<M>,
+    forward_limit: Re<MessageForwardLimit<M>>,
+) {
+    let count = messages.len_previous();
+    if count > usize::from(u16::MAX) + 1 {
+        // `MessageId` is a `u16`, so the ids of these messages are no longer unique
+        error!(
+            count,
+            message_type = type_name::<M>(),
+            "message ids wrapped around"
+        );
+    }
+
+    let mut skip = 0;
+    if let Some(limit) = forward_limit.limit
+        && count > limit
+    {
+        warn!(
+            count,
+            limit,
+            message_type = type_name::<M>(),
+            overflow = ?forward_limit.overflow,
+            "too many messages"
+        );
+        if forward_limit.overflow == MessageOverflow::DropOldest {
+            skip = count - limit;
+        }
+    }
+
+    for message in messages.iter_previous().skip(skip) {
+        internal_game.game.message(message);
+    }
+}
+
+/// Registers the message type `M`, if needed, and lets the [`Application`] receive it.
+pub struct GameMessagePlugin<G: Application + ApplicationMessage<M>, M: Message> {
+    pub forward_limit: Option<usize>,
+    pub overflow: MessageOverflow,
+    pub phantom_data: PhantomData<(G, M)>,
+}
+
+impl<G: Application + ApplicationMessage<M>, M: Message> Default for GameMessagePlugin<G, M> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<G: Application + ApplicationMessage<M>, M: Message> GameMessagePlugin<G, M> {
+    #[must_use]
+    pub const fn new() -> Self {
+        Self {
+            forward_limit: None,
+            overflow: MessageOverflow::Warn,
+            phantom_data: PhantomData,
+        }
+    }
+
+    /// Forwards at most `forward_limit` messages each update, see [`MessageOverflow`].
+    /// The messages that are not forwarded are still in the `Messages<M>` queue.
+    #[must_use]
+    pub const fn with_forward_limit(forward_limit: usize, overflow: MessageOverflow) -> Self {
+        Self {
+            forward_limit: Some(forward_limit),
+            overflow,
+            phantom_data: PhantomData,
+        }
+    }
+}
+
+impl<G: Application + ApplicationMessage<M>, M: Message> Plugin for GameMessagePlugin<G, M> {
+    fn build(&self, app: &mut App) {
+        if app.get_messages::<M>().is_none() {
+            app.create_message_type::<M>();
+        }
+        app.insert_resource(MessageForwardLimit::<M> {
+            limit: self.forward_limit,
+            overflow: self.overflow,
+            phantom_data: PhantomData,
+        });
+        app.add_system(Update, message_tick::<G, M>);
+    }
+}
+
 impl<G: Application> Plugin for GamePlugin<G> {
     fn post_initialization(&self, app: &mut App) {
         debug!("calling WgpuGame::new()");
 
         let all_resources = app.resources_mut();
+        if !all_resources.contains::<GamepadRumble>() {
+            all_resources.insert(GamepadRumble::default());
+        }
+        all_resources.insert(Keys::default());
+        all_resources.insert(Mouse::default());
+        all_resources.insert(LoadingAssets::default());
         let internal_game = Game::<G>::new(all_resources);
         app.insert_local_resource(internal_game);
+        let voices = Voices::new(app.local_resources().fetch::<AudioMixer>());
+        app.insert_local_resource(voices);
 
         app.add_system(Update, gamepad_input_tick::<G>);
         app.add_system(Update, keyboard_input_tick::<G>);
@@ -2,4 +2,4 @@
  * Copyright (c) Peter Bjorklund. All rights reserved. https://github.com/mireforge/mireforge
  * Licensed under the MIT License. See LICENSE in the project root for license information.
  */
-pub use crate::Application;
+pub use crate::{Application, ApplicationMessage, GameMessagePlugin, MessageOverflow};
@@ -12,8 +12,10 @@ use limnus_assets_loader::{AssetLoader, ConversionError, WrappedAssetLoaderRegis
 use limnus_local_resource::LocalResourceStorage;
 use limnus_resource::ResourceStorage;
 use limnus_wgpu_window::BasicDeviceInfo;
+use mireforge_render_wgpu::plugin::TextureOptionsByName;
 use mireforge_render_wgpu::{Render, Texture};
-use tracing::debug;
+use mireforge_wgpu_sprites::TextureLoadOptions;
+use tracing::{debug, error};
 
 pub struct MaterialPlugin;
 
@@ -64,18 +66,42 @@ impl AssetLoader for MaterialWgpuProcessor {
         let dynamic_image = image::load_from_memory_with_format(octets, image::ImageFormat::Png)
             .expect("Failed to load image");
 
+        let max_dimension = device_info.device.limits().max_texture_dimension_2d;
+        if dynamic_image.width() > max_dimension || dynamic_image.height() > max_dimension {
+            error!(
+                %name,
+                width = dynamic_image.width(),
+                height = dynamic_image.height(),
+                max_dimension,
+                "texture is larger than the gpu supports, split it or scale it down"
+            );
+            return Err(ConversionError::WrongFormat);
+        }
+
         debug!(?name, "creating texture");
-        let wgpu_texture = mireforge_wgpu_sprites::load_texture_from_memory(
+        let texture_options = resources
+            .get::<TextureOptionsByName>()
+            .map(|options| options.get(name.value()))
+            .unwrap_or_default();
+        let options = TextureLoadOptions {
+            premultiply_alpha: texture_options.premultiply_alpha,
+            generate_mipmaps: texture_options.generate_mipmaps,
+        };
+        let wgpu_texture = mireforge_wgpu_sprites::load_texture_from_memory_ex(
             &device_info.device,
             &device_info.queue,
             dynamic_image,
             name.value(),
+            options,
         );
 
         {
             let mireforge_render_wgpu = resources.fetch_mut::<Render>();
-            let wgpu_material =
-                mireforge_render_wgpu.texture_resource_from_texture(&wgpu_texture, name.value());
+            let wgpu_material = mireforge_render_wgpu.texture_resource_from_texture_ex(
+                &wgpu_texture,
+                name.value(),
+                texture_options.filter,
+            );
 
             let image_assets = resources.fetch_mut::<Assets<Texture>>();
             image_assets.set_raw(id, wgpu_material);
@@ -24,10 +24,10 @@ pub use {
     mireforge_font::*,
     mireforge_game::prelude::*,
     mireforge_game_assets::*,
-    mireforge_game_audio::{Audio, SoundHandle},
+    mireforge_game_audio::{Audio, Bus, MusicHandle, SoundHandle},
     mireforge_material::prelude::*,
     mireforge_render::prelude::*,
     mireforge_render_wgpu::prelude::*,
-    monotonic_time_rs::Millis,
+    monotonic_time_rs::{Millis, MillisDuration},
     tracing::{debug, error, info, trace, warn},
 };
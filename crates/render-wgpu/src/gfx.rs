@@ -1,17 +1,45 @@
 use crate::{
-    FixedAtlas, FontAndMaterial, FrameLookup, MaterialRef, NineSliceAndMaterial, QuadParams,
-    SpriteParams,
+    AnimationState, FixedAtlas, FontAndMaterial, FrameLookup, LayerId, MaterialRef,
+    NineSliceAndMaterial, QuadParams, SpriteParams, TextParams, TextureFilter, TextureRef,
 };
 use int_math::{URect, UVec2, Vec2, Vec3};
-use mireforge_render::{AspectRatio, Color, ViewportStrategy, VirtualScale};
-use monotonic_time_rs::Millis;
+use mireforge_render::{AspectRatio, Color, ScreenEffect, ViewportStrategy, VirtualScale};
+use monotonic_time_rs::{Millis, MillisDuration};
+use std::ops::Range;
 
 pub trait Gfx {
     fn sprite_atlas_frame(&mut self, position: Vec3, frame: u16, atlas: &impl FrameLookup);
+    /// Draws the frame of `animation` for the current render time.
+    fn draw_animation(
+        &mut self,
+        position: Vec3,
+        atlas: &impl FrameLookup,
+        animation: &AnimationState,
+    );
     fn sprite_atlas(&mut self, position: Vec3, atlas_rect: URect, material_ref: &MaterialRef);
     fn draw_sprite(&mut self, position: Vec3, material_ref: &MaterialRef);
     fn draw_sprite_ex(&mut self, position: Vec3, material_ref: &MaterialRef, params: &SpriteParams);
+    fn draw_sprite_outlined(
+        &mut self,
+        position: Vec3,
+        material_ref: &MaterialRef,
+        params: &SpriteParams,
+        outline_color: Color,
+    );
     fn quad(&mut self, position: Vec3, size: UVec2, color: Color);
+    fn quad_gradient(&mut self, position: Vec3, size: UVec2, top_color: Color, bottom_color: Color);
+    fn quad_gradient_horizontal(
+        &mut self,
+        position: Vec3,
+        size: UVec2,
+        left_color: Color,
+        right_color: Color,
+    );
+    fn circle(&mut self, position: Vec3, radius: u16, color: Color);
+    fn ellipse(&mut self, position: Vec3, radii: UVec2, color: Color);
+    fn rounded_quad(&mut self, position: Vec3, size: UVec2, radius: u16, color: Color);
+    fn line(&mut self, from: Vec2, to: Vec2, thickness: u16, color: Color, z: i16);
+    fn polyline(&mut self, points: &[Vec2], thickness: u16, color: Color, z: i16);
     fn draw_with_mask(
         &mut self,
         position: Vec3,
@@ -20,6 +48,16 @@ pub trait Gfx {
         alpha_masked: &MaterialRef,
     );
 
+    /// Same as `draw_with_mask`, but the alpha mask is sampled `mask_offset` texels away.
+    fn draw_with_mask_offset(
+        &mut self,
+        position: Vec3,
+        size: UVec2,
+        mask_offset: Vec2,
+        color: Color,
+        alpha_masked: &MaterialRef,
+    );
+
     fn nine_slice(
         &mut self,
         position: Vec3,
@@ -37,7 +75,23 @@ pub trait Gfx {
     );
 
     fn set_origin(&mut self, position: Vec2);
+    /// Fractional origin for smooth scrolling, see [`crate::Render::set_origin_f32`].
+    fn set_origin_f32(&mut self, x: f32, y: f32);
+    fn set_camera(&mut self, center: Vec2, zoom: f32, rotation_radians: f32);
+
+    fn push_clip_rect(&mut self, rect: URect);
+    fn set_layer(&mut self, layer: LayerId);
+    fn set_render_target(&mut self, target: Option<&TextureRef>);
+    fn pop_clip_rect(&mut self);
+
     fn set_clear_color(&mut self, color: Color);
+    fn set_layer_clear(&mut self, layer: LayerId, color: Option<Color>);
+    fn set_letterbox_color(&mut self, color: Color);
+    fn set_letterbox_texture(&mut self, texture: Option<&TextureRef>);
+    fn set_screen_effect(&mut self, screen_effect: ScreenEffect);
+
+    /// Vsync is on by default. Without it the frame rate is uncapped, if the platform allows it.
+    fn set_vsync(&mut self, enabled: bool);
 
     fn tilemap_params(
         &mut self,
@@ -50,8 +104,34 @@ pub trait Gfx {
 
     fn text_draw(&mut self, position: Vec3, text: &str, font_ref: &FontAndMaterial, color: &Color);
 
+    fn text_draw_ex(
+        &mut self,
+        position: Vec3,
+        text: &str,
+        font_ref: &FontAndMaterial,
+        color: &Color,
+        params: &TextParams,
+    );
+
+    /// Draws text where the chars inside each span (char index range) get the span color.
+    /// Chars not covered by any span use `color`.
+    fn text_draw_spans(
+        &mut self,
+        position: Vec3,
+        text: &str,
+        font_ref: &FontAndMaterial,
+        color: &Color,
+        color_spans: &[(Range<usize>, Color)],
+    );
+
     #[must_use]
     fn now(&self) -> Millis;
+    fn frame_delta(&self) -> MillisDuration;
+    /// How far `now` is between the latest fixed tick and the next, from `0.0` to `1.0`, for
+    /// interpolating between the previous and the current simulation state.
+    /// Always `0.0` when the logic is not ticked with a fixed step.
+    fn interpolation_alpha(&self) -> f32;
+    fn fps(&self) -> f32;
 
     #[must_use]
     fn physical_aspect_ratio(&self) -> AspectRatio;
@@ -64,6 +144,9 @@ pub trait Gfx {
     #[must_use]
     fn viewport(&self) -> &ViewportStrategy;
 
+    /// `None` picks the filter from the viewport strategy, see [`crate::Render::set_blit_filter`].
+    fn set_blit_filter(&mut self, filter: Option<TextureFilter>);
+
     fn set_scale(&mut self, scale_factor: VirtualScale);
 
     fn set_virtual_size(&mut self, virtual_size: UVec2);
@@ -1,17 +1,28 @@
 use crate::gfx::Gfx;
 use crate::{
-    FixedAtlas, FontAndMaterial, FrameLookup, MaterialRef, NineSliceAndMaterial, QuadParams,
-    Render, RenderItem, Renderable, SpriteParams, Text, TileMap, to_wgpu_color,
+    AnimationState, FixedAtlas, FontAndMaterial, FrameLookup, LayerId, MaterialRef,
+    NineSliceAndMaterial, QuadParams, Render, RenderItem, Renderable, SpriteParams, Text,
+    TextAlign, TextParams, TextureFilter, TextureRef, TileMap,
 };
 use int_math::{URect, UVec2, Vec2, Vec3};
-use mireforge_render::{AspectRatio, Color, ViewportStrategy, VirtualScale};
-use monotonic_time_rs::Millis;
+use mireforge_render::{AspectRatio, Color, ScreenEffect, ViewportStrategy, VirtualScale};
+use monotonic_time_rs::{Millis, MillisDuration};
+use std::ops::Range;
 
 impl Gfx for Render {
     fn sprite_atlas_frame(&mut self, position: Vec3, frame: u16, atlas: &impl FrameLookup) {
         self.sprite_atlas_frame(position, frame, atlas);
     }
 
+    fn draw_animation(
+        &mut self,
+        position: Vec3,
+        atlas: &impl FrameLookup,
+        animation: &AnimationState,
+    ) {
+        self.draw_animation(position, atlas, animation, self.last_render_at);
+    }
+
     fn sprite_atlas(&mut self, position: Vec3, atlas_rect: URect, material_ref: &MaterialRef) {
         self.sprite_atlas(position, atlas_rect, material_ref);
     }
@@ -29,10 +40,60 @@ impl Gfx for Render {
         self.draw_sprite_ex(position, material_ref, *params);
     }
 
+    fn draw_sprite_outlined(
+        &mut self,
+        position: Vec3,
+        material_ref: &MaterialRef,
+        params: &SpriteParams,
+        outline_color: Color,
+    ) {
+        self.draw_sprite_outlined(position, material_ref, *params, outline_color);
+    }
+
     fn quad(&mut self, position: Vec3, size: UVec2, color: Color) {
         self.draw_quad(position, size, color);
     }
 
+    fn quad_gradient(
+        &mut self,
+        position: Vec3,
+        size: UVec2,
+        top_color: Color,
+        bottom_color: Color,
+    ) {
+        self.draw_quad_gradient(position, size, top_color, bottom_color);
+    }
+
+    fn quad_gradient_horizontal(
+        &mut self,
+        position: Vec3,
+        size: UVec2,
+        left_color: Color,
+        right_color: Color,
+    ) {
+        self.draw_quad_gradient_horizontal(position, size, left_color, right_color);
+    }
+
+    fn circle(&mut self, position: Vec3, radius: u16, color: Color) {
+        self.draw_circle(position, radius, color);
+    }
+
+    fn ellipse(&mut self, position: Vec3, radii: UVec2, color: Color) {
+        self.draw_ellipse(position, radii, color);
+    }
+
+    fn rounded_quad(&mut self, position: Vec3, size: UVec2, radius: u16, color: Color) {
+        self.draw_rounded_quad(position, size, radius, color);
+    }
+
+    fn line(&mut self, from: Vec2, to: Vec2, thickness: u16, color: Color, z: i16) {
+        self.draw_line(from, to, thickness, color, z);
+    }
+
+    fn polyline(&mut self, points: &[Vec2], thickness: u16, color: Color, z: i16) {
+        self.draw_polyline(points, thickness, color, z);
+    }
+
     fn quad_ex(&mut self, position: Vec3, size: UVec2, color: Color, params: QuadParams) {
         self.draw_quad_ex(position, size, color, params);
     }
@@ -47,6 +108,17 @@ impl Gfx for Render {
         self.push_mask(position, size, color, alpha_masked);
     }
 
+    fn draw_with_mask_offset(
+        &mut self,
+        position: Vec3,
+        size: UVec2,
+        mask_offset: Vec2,
+        color: Color,
+        alpha_masked: &MaterialRef,
+    ) {
+        self.push_mask_with_offset(position, size, mask_offset, color, alpha_masked);
+    }
+
     fn nine_slice(
         &mut self,
         position: Vec3,
@@ -68,11 +140,59 @@ impl Gfx for Render {
     }
 
     fn set_origin(&mut self, position: Vec2) {
-        self.origin = position;
+        self.set_origin(position);
+    }
+
+    fn set_origin_f32(&mut self, x: f32, y: f32) {
+        self.set_origin_f32(x, y);
+    }
+
+    fn set_camera(&mut self, center: Vec2, zoom: f32, rotation_radians: f32) {
+        self.set_camera(center, zoom, rotation_radians);
+    }
+
+    fn set_render_target(&mut self, target: Option<&TextureRef>) {
+        self.set_render_target(target);
+    }
+
+    fn set_layer(&mut self, layer: LayerId) {
+        self.set_layer(layer);
+    }
+
+    fn push_clip_rect(&mut self, rect: URect) {
+        self.push_clip_rect(rect);
+    }
+
+    fn pop_clip_rect(&mut self) {
+        self.pop_clip_rect();
     }
 
     fn set_clear_color(&mut self, color: Color) {
-        self.clear_color = to_wgpu_color(color);
+        self.set_clear_color(color);
+    }
+
+    fn set_layer_clear(&mut self, layer: LayerId, color: Option<Color>) {
+        self.set_layer_clear(layer, color);
+    }
+
+    fn set_letterbox_color(&mut self, color: Color) {
+        self.set_letterbox_color(color);
+    }
+
+    fn set_letterbox_texture(&mut self, texture: Option<&TextureRef>) {
+        self.set_letterbox_texture(texture);
+    }
+
+    fn set_screen_effect(&mut self, screen_effect: ScreenEffect) {
+        self.set_screen_effect(screen_effect);
+    }
+
+    fn set_vsync(&mut self, enabled: bool) {
+        self.set_present_mode(if enabled {
+            wgpu::PresentMode::Fifo
+        } else {
+            wgpu::PresentMode::Immediate
+        });
     }
 
     fn tilemap_params(
@@ -86,10 +206,15 @@ impl Gfx for Render {
         self.items.push(RenderItem {
             position,
             material_ref: atlas_ref.material.clone(),
+            clip: self.current_clip(),
+            layer: self.current_layer,
+            target: self.current_target,
             renderable: Renderable::TileMap(TileMap {
-                tiles_data_grid_size: UVec2::new(width, tiles.len() as u16 / width),
+                tiles_data_grid_size: UVec2::new(width, TileMap::grid_height(tiles.len(), width)),
                 cell_count_size: atlas_ref.cell_count_size,
                 one_cell_size: atlas_ref.one_cell_size,
+                margin: atlas_ref.margin,
+                spacing: atlas_ref.spacing,
                 tiles: Vec::from(tiles),
                 scale,
             }),
@@ -106,14 +231,85 @@ impl Gfx for Render {
         self.items.push(RenderItem {
             position,
             material_ref: font_and_mat.material_ref.clone(),
+            clip: self.current_clip(),
+            layer: self.current_layer,
+            target: self.current_target,
             renderable: Renderable::Text(Text {
                 text: text.to_string(),
                 font_ref: (&font_and_mat.font_ref).into(),
                 color: *color,
+                color_spans: Vec::new(),
+                align: TextAlign::default(),
+                shadow: None,
+                outline: None,
             }),
         });
     }
 
+    fn text_draw_ex(
+        &mut self,
+        position: Vec3,
+        text: &str,
+        font_and_mat: &FontAndMaterial,
+        color: &Color,
+        params: &TextParams,
+    ) {
+        self.items.push(RenderItem {
+            position,
+            material_ref: font_and_mat.material_ref.clone(),
+            clip: self.current_clip(),
+            layer: self.current_layer,
+            target: self.current_target,
+            renderable: Renderable::Text(Text {
+                text: text.to_string(),
+                font_ref: (&font_and_mat.font_ref).into(),
+                color: *color,
+                color_spans: Vec::new(),
+                align: params.align,
+                shadow: params.shadow,
+                outline: params.outline,
+            }),
+        });
+    }
+
+    fn text_draw_spans(
+        &mut self,
+        position: Vec3,
+        text: &str,
+        font_and_mat: &FontAndMaterial,
+        color: &Color,
+        color_spans: &[(Range<usize>, Color)],
+    ) {
+        self.items.push(RenderItem {
+            position,
+            material_ref: font_and_mat.material_ref.clone(),
+            clip: self.current_clip(),
+            layer: self.current_layer,
+            target: self.current_target,
+            renderable: Renderable::Text(Text {
+                text: text.to_string(),
+                font_ref: (&font_and_mat.font_ref).into(),
+                color: *color,
+                color_spans: color_spans.to_vec(),
+                align: TextAlign::default(),
+                shadow: None,
+                outline: None,
+            }),
+        });
+    }
+
+    fn frame_delta(&self) -> MillisDuration {
+        self.frame_delta()
+    }
+
+    fn interpolation_alpha(&self) -> f32 {
+        self.interpolation_alpha()
+    }
+
+    fn fps(&self) -> f32 {
+        self.fps()
+    }
+
     fn now(&self) -> Millis {
         self.last_render_at
     }
@@ -131,11 +327,15 @@ impl Gfx for Render {
     }
 
     fn set_viewport(&mut self, viewport_strategy: ViewportStrategy) {
-        self.viewport_strategy = viewport_strategy;
+        self.set_viewport_strategy(viewport_strategy);
     }
 
     fn viewport(&self) -> &ViewportStrategy {
-        &self.viewport_strategy
+        self.viewport_strategy()
+    }
+
+    fn set_blit_filter(&mut self, filter: Option<TextureFilter>) {
+        self.set_blit_filter(filter);
     }
 
     fn set_scale(&mut self, scale_factor: VirtualScale) {
@@ -7,7 +7,7 @@ mod gfx_impl;
 pub mod plugin;
 pub mod prelude;
 
-use int_math::{URect, UVec2, Vec2, Vec3};
+use int_math::{Rect, URect, UVec2, Vec2, Vec3};
 use limnus_assets::Assets;
 use limnus_assets::prelude::{Asset, Id, WeakId};
 use limnus_resource::prelude::Resource;
@@ -16,17 +16,23 @@ use mireforge_font::Font;
 use mireforge_font::FontRef;
 use mireforge_font::WeakFontRef;
 use mireforge_render::prelude::*;
-use mireforge_wgpu::create_nearest_sampler;
+use mireforge_wgpu::{
+    create_linear_clamp_sampler, create_nearest_sampler, create_trilinear_sampler,
+};
 use mireforge_wgpu_sprites::{
-    ShaderInfo, SpriteInfo, SpriteInstanceUniform, create_texture_and_sampler_bind_group_ex,
-    create_texture_and_sampler_group_layout,
+    ScreenEffectUniform, ShaderInfo, SpriteInfo, SpriteInstanceUniform,
+    create_camera_uniform_bind_group, create_camera_uniform_buffer, create_debug_shader_info,
+    create_depth_texture_view, create_quad_matrix_and_uv_instance_buffer,
+    create_texture_and_sampler_bind_group_ex, create_texture_and_sampler_group_layout,
 };
-use monotonic_time_rs::Millis;
+use monotonic_time_rs::{Millis, MillisDuration};
 use std::cmp::Ordering;
+use std::collections::HashMap;
 use std::fmt::{Debug, Display, Formatter};
 use std::mem::swap;
+use std::ops::Range;
 use std::sync::Arc;
-use tracing::{debug, trace};
+use tracing::{debug, error, trace, warn};
 use wgpu::{
     BindGroup, BindGroupLayout, Buffer, CommandEncoder, Device, RenderPipeline, TextureFormat,
     TextureView,
@@ -39,6 +45,9 @@ pub type WeakMaterialRef = Arc<Material>;
 pub type TextureRef = Id<Texture>;
 pub type WeakTextureRef = WeakId<Texture>;
 
+/// Called with the command encoder and the virtual surface, see [`Render::add_custom_pass`].
+pub type CustomPass = Box<dyn FnMut(&mut CommandEncoder, &TextureView) + Send + Sync>;
+
 pub trait FrameLookup {
     fn lookup(&self, frame: u16) -> (&MaterialRef, URect);
 }
@@ -49,25 +58,54 @@ pub struct FixedAtlas {
     pub texture_size: UVec2,
     pub one_cell_size: UVec2,
     pub cell_count_size: UVec2,
+    /// Space between the texture edges and the outermost cells
+    pub margin: UVec2,
+    /// Space between two neighboring cells
+    pub spacing: UVec2,
 }
 
 impl FixedAtlas {
+    /// `margin` and `spacing` are the padding around and between the cells,
+    /// as exported by e.g. `TexturePacker` or Tiled with extrusion.
+    /// Use zero for both for tightly packed cells.
+    ///
     /// # Panics
     ///
+    /// If not even one cell fits in the texture, e.g. when the margins are larger than the texture.
     #[must_use]
-    pub fn new(one_cell_size: UVec2, texture_size: UVec2, material_ref: MaterialRef) -> Self {
+    pub fn new(
+        one_cell_size: UVec2,
+        texture_size: UVec2,
+        margin: UVec2,
+        spacing: UVec2,
+        material_ref: MaterialRef,
+    ) -> Self {
+        // In u32, so large margins and spacings can not overflow
+        let cell_count = |texture: u16, margin: u16, spacing: u16, cell: u16| {
+            let usable =
+                u32::from(texture).saturating_sub(u32::from(margin) * 2) + u32::from(spacing);
+            let count = usable
+                .checked_div(u32::from(cell) + u32::from(spacing))
+                .unwrap_or(0);
+            u16::try_from(count).unwrap_or(u16::MAX)
+        };
         let cell_count_size = UVec2::new(
-            texture_size.x / one_cell_size.x,
-            texture_size.y / one_cell_size.y,
+            cell_count(texture_size.x, margin.x, spacing.x, one_cell_size.x),
+            cell_count(texture_size.y, margin.y, spacing.y, one_cell_size.y),
         );
 
-        assert_ne!(cell_count_size.x, 0, "illegal texture and one cell size");
+        assert!(
+            cell_count_size.x != 0 && cell_count_size.y != 0,
+            "no cells of {one_cell_size:?} fit in the texture {texture_size:?} with margin {margin:?}"
+        );
 
         Self {
             material: material_ref,
             texture_size,
             one_cell_size,
             cell_count_size,
+            margin,
+            spacing,
         }
     }
 }
@@ -80,8 +118,8 @@ impl FrameLookup for FixedAtlas {
         (
             &self.material,
             URect::new(
-                x * self.one_cell_size.x,
-                y * self.one_cell_size.y,
+                self.margin.x + x * (self.one_cell_size.x + self.spacing.x),
+                self.margin.y + y * (self.one_cell_size.y + self.spacing.y),
                 self.one_cell_size.x,
                 self.one_cell_size.y,
             ),
@@ -89,10 +127,148 @@ impl FrameLookup for FixedAtlas {
     }
 }
 
+/// Atlas with arbitrary sized frames, e.g. from a packer manifest with named rects.
+#[derive(Debug, Clone)]
+pub struct Atlas {
+    pub material: MaterialRef,
+    pub frames: Vec<URect>,
+    names: HashMap<String, u16>,
+}
+
+impl Atlas {
+    #[must_use]
+    pub fn new(frames: Vec<URect>, material_ref: MaterialRef) -> Self {
+        Self {
+            material: material_ref,
+            frames,
+            names: HashMap::new(),
+        }
+    }
+
+    /// The frame index is the position in `named_frames`.
+    ///
+    /// # Panics
+    ///
+    #[must_use]
+    pub fn from_named(named_frames: Vec<(String, URect)>, material_ref: MaterialRef) -> Self {
+        let mut frames = Vec::with_capacity(named_frames.len());
+        let mut names = HashMap::with_capacity(named_frames.len());
+        for (name, rect) in named_frames {
+            let index = u16::try_from(frames.len()).expect("too many frames in atlas");
+            names.insert(name, index);
+            frames.push(rect);
+        }
+
+        Self {
+            material: material_ref,
+            frames,
+            names,
+        }
+    }
+
+    #[must_use]
+    pub fn frame_by_name(&self, name: &str) -> Option<u16> {
+        self.names.get(name).copied()
+    }
+}
+
+impl FrameLookup for Atlas {
+    fn lookup(&self, frame: u16) -> (&MaterialRef, URect) {
+        (&self.material, self.frames[frame as usize])
+    }
+}
+
+/// Atlas frames that are played back at a fixed rate.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AnimationClip {
+    pub frames: Vec<u16>,
+    pub fps: u8,
+    pub looping: bool,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AnimationState {
+    pub clip: AnimationClip,
+    started_at: Millis,
+}
+
+impl AnimationState {
+    #[must_use]
+    pub const fn new(clip: AnimationClip, now: Millis) -> Self {
+        Self {
+            clip,
+            started_at: now,
+        }
+    }
+
+    pub const fn restart(&mut self, now: Millis) {
+        self.started_at = now;
+    }
+
+    /// Switches to `clip` and restarts, unless it is already the playing clip.
+    pub fn play(&mut self, clip: &AnimationClip, now: Millis) {
+        if self.clip != *clip {
+            self.clip = clip.clone();
+            self.started_at = now;
+        }
+    }
+
+    fn frames_since_start(&self, now: Millis) -> u64 {
+        now.checked_duration_since_ms(self.started_at)
+            .map_or(0, |elapsed| {
+                elapsed.as_millis() * u64::from(self.clip.fps) / 1000
+            })
+    }
+
+    /// Index into [`AnimationClip::frames`] for `now`.
+    /// A clip that is not looping stays on its last frame.
+    #[must_use]
+    pub fn frame_index(&self, now: Millis) -> usize {
+        let count = self.clip.frames.len() as u64;
+        if count == 0 {
+            return 0;
+        }
+        let frames = self.frames_since_start(now);
+        if self.clip.looping {
+            (frames % count) as usize
+        } else {
+            frames.min(count - 1) as usize
+        }
+    }
+
+    /// The atlas frame to show at `now`.
+    ///
+    /// # Panics
+    /// If the clip has no frames.
+    #[must_use]
+    pub fn frame(&self, now: Millis) -> u16 {
+        self.clip.frames[self.frame_index(now)]
+    }
+
+    #[must_use]
+    pub fn is_finished(&self, now: Millis) -> bool {
+        !self.clip.looping && self.frames_since_start(now) >= self.clip.frames.len() as u64
+    }
+}
+
 #[derive(Debug)]
 pub struct NineSliceAndMaterial {
     pub slices: Slices,
     pub material_ref: MaterialRef,
+    pub mode: NineSliceMode,
+    pub draw_center: bool,
+}
+
+/// Counters for the latest rendered frame, see [`Render::last_frame_stats`].
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct RenderStats {
+    pub batches: usize,
+    pub quads: usize,
+    pub draw_calls: usize,
+    pub sprites: usize,
+    pub text_glyphs: usize,
+    /// `true` if quads were dropped because they were over the [`QuadLimits`].
+    pub quads_dropped: bool,
 }
 
 #[derive(Debug, PartialEq, Eq, Clone)]
@@ -101,6 +277,49 @@ pub struct FontAndMaterial {
     pub material_ref: MaterialRef,
 }
 
+/// Drops the quads after `start + limit`, and returns how many that were dropped.
+fn truncate_quads(quads: &mut Vec<SpriteInstanceUniform>, start: usize, limit: usize) -> usize {
+    let end = start + limit;
+    let dropped = quads.len().saturating_sub(end);
+    quads.truncate(end);
+    dropped
+}
+
+/// Number of quads that fit in the instance `buffer`.
+fn instance_capacity(buffer: &Buffer) -> usize {
+    buffer.size() as usize / size_of::<SpriteInstanceUniform>()
+}
+
+/// Converts the bits of an IEEE 754 half precision float.
+fn f16_to_f32(bits: u16) -> f32 {
+    let sign = if bits & 0x8000 == 0 { 1.0 } else { -1.0 };
+    let exponent = i32::from((bits >> 10) & 0x1f);
+    let fraction = f32::from(bits & 0x3ff) / 1024.0;
+    match exponent {
+        0 => sign * fraction * 2.0_f32.powi(-14),
+        0x1f if fraction == 0.0 => sign * f32::INFINITY,
+        0x1f => f32::NAN,
+        _ => sign * (1.0 + fraction) * 2.0_f32.powi(exponent - 15),
+    }
+}
+
+/// Rounds the x and y translation of `matrix` to whole pixels.
+fn snap_translation(matrix: Matrix4) -> Matrix4 {
+    let column = |index: usize| matrix[index].0;
+    let translation = column(3);
+    Matrix4::from([
+        column(0),
+        column(1),
+        column(2),
+        [
+            translation[0].round(),
+            translation[1].round(),
+            translation[2],
+            translation[3],
+        ],
+    ])
+}
+
 fn to_wgpu_color(c: Color) -> wgpu::Color {
     let f = c.to_f64();
     wgpu::Color {
@@ -115,6 +334,12 @@ fn to_wgpu_color(c: Color) -> wgpu::Color {
 struct RenderItem {
     position: Vec3,
     material_ref: MaterialRef,
+    /// Active clip rectangle when the item was pushed, if any
+    clip: Option<URect>,
+    /// Active layer when the item was pushed
+    layer: LayerId,
+    /// Render target that was active when the item was pushed, `None` for the virtual surface
+    target: Option<WeakTextureRef>,
 
     renderable: Renderable,
 }
@@ -124,38 +349,110 @@ pub struct Text {
     text: String,
     font_ref: WeakFontRef,
     color: Color,
+    /// Char index ranges that override `color`. Later spans take precedence.
+    color_spans: Vec<(Range<usize>, Color)>,
+    align: TextAlign,
+    shadow: Option<(Vec2, Color)>,
+    outline: Option<Color>,
+}
+
+impl Text {
+    fn color_for_char(&self, char_index: usize) -> Color {
+        self.color_spans
+            .iter()
+            .rev()
+            .find(|(range, _)| range.contains(&char_index))
+            .map_or(self.color, |(_, color)| *color)
+    }
 }
 
 #[derive(Debug)]
 enum Renderable {
     Sprite(Sprite),
     QuadColor(QuadColor),
+    QuadGradient(QuadGradient),
+    Circle(Circle),
+    RoundedQuad(RoundedQuad),
+    Line(Line),
     NineSlice(NineSlice),
-    NineSliceStretch(NineSlice),
     TileMap(TileMap),
     Text(Text),
-    Mask(UVec2, Color),
+    /// Diffuse texture offset, mask texture offset in texels and color.
+    Mask(UVec2, Vec2, Color),
 }
 
+/// Set in the gradient direction of quads and lines to smooth their edges in the shader.
+const QUAD_ANTIALIAS_FLAG: u32 = 0b10;
+
 const MAXIMUM_QUADS_FOR_RENDER_ITEM: usize = 1024;
 const MAXIMUM_QUADS_IN_A_BATCH: usize = 4096;
 const MAXIMUM_QUADS_IN_ONE_RENDER: usize = MAXIMUM_QUADS_IN_A_BATCH * 8;
 
+/// Number of instance buffers that are used round-robin, one per frame, so the quads of a frame
+/// are not written to a buffer that the GPU can still be reading for an earlier frame.
+pub const INSTANCE_BUFFER_COUNT: usize = 3;
+
+/// How many quads (sprites, glyphs, tiles) that can be rendered, see [`Render::set_quad_limits`].
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct QuadLimits {
+    /// For one pushed item, e.g. a tilemap or a text.
+    pub per_render_item: usize,
+    /// For consecutive items that share material, clip and layer.
+    pub per_batch: usize,
+    /// For the whole frame. The instance buffer is grown to hold this many quads.
+    pub per_render: usize,
+}
+
+impl Default for QuadLimits {
+    fn default() -> Self {
+        Self {
+            per_render_item: MAXIMUM_QUADS_FOR_RENDER_ITEM,
+            per_batch: MAXIMUM_QUADS_IN_A_BATCH,
+            per_render: MAXIMUM_QUADS_IN_ONE_RENDER,
+        }
+    }
+}
+
 #[derive(Resource)]
 pub struct Render {
     virtual_surface_texture_view: TextureView,
     virtual_surface_texture: wgpu::Texture,
+    virtual_surface_depth_view: TextureView,
+    flush_depth: Option<(wgpu::Extent3d, TextureView)>,
+    depth_buffer_enabled: bool,
+    half_texel_inset: bool,
+    pixel_snap: bool,
+    quad_antialiasing: bool,
     virtual_to_surface_bind_group: BindGroup,
     index_buffer: Buffer,  // Only indices for a single identity quad
     vertex_buffer: Buffer, // Only one identity quad (0,0,1,1)
     sampler: wgpu::Sampler,
+    trilinear_sampler: wgpu::Sampler,
     virtual_to_screen_shader_info: ShaderInfo,
+    crt_screen_shader_info: ShaderInfo,
+    screen_effect: ScreenEffect,
+    screen_effect_uniform_buffer: Buffer,
+    screen_effect_bind_group: BindGroup,
     pub normal_sprite_pipeline: ShaderInfo,
+    pub multiply_sprite_pipeline: ShaderInfo,
+    pub premultiplied_sprite_pipeline: ShaderInfo,
+    pub outline_sprite_pipeline: ShaderInfo,
+    pub opaque_sprite_pipeline: ShaderInfo,
     pub quad_shader_info: ShaderInfo,
+    pub circle_shader_info: ShaderInfo,
+    pub rounded_quad_shader_info: ShaderInfo,
     pub mask_shader_info: ShaderInfo,
     pub light_shader_info: ShaderInfo,
+    overdraw_pipeline: ShaderInfo,
+    /// `None` if the device does not support `POLYGON_MODE_LINE`
+    wireframe_pipeline: Option<ShaderInfo>,
+    debug_draw: DebugDraw,
     physical_surface_size: UVec2,
+    present_mode: wgpu::PresentMode,
+    surface_needs_configure: bool,
     viewport_strategy: ViewportStrategy,
+    blit_filter_override: Option<TextureFilter>,
+    virtual_to_surface_filter: TextureFilter,
     virtual_surface_size: UVec2,
     // Group 0
     camera_bind_group: BindGroup,
@@ -166,7 +463,9 @@ pub struct Render {
     texture_sampler_bind_group_layout: BindGroupLayout,
 
     // Group 1
-    quad_matrix_and_uv_instance_buffer: Buffer,
+    quad_matrix_and_uv_instance_buffers: Vec<Buffer>,
+    /// Index of the instance buffer for the current frame
+    instance_buffer_index: usize,
 
     device: Arc<wgpu::Device>,
     queue: Arc<wgpu::Queue>, // Queue to talk to device
@@ -175,16 +474,38 @@ pub struct Render {
     items: Vec<RenderItem>,
     //fonts: Vec<FontAndMaterialRef>,
     origin: Vec2,
+    /// Sub-pixel part of the origin, in `0.0..1.0`, only applied in the view projection.
+    origin_fraction: (f32, f32),
 
     // Cache
-    batch_offsets: Vec<(WeakMaterialRef, u32, u32)>,
-    viewport: URect,
+    batch_offsets: Vec<Batch>,
+    clip_stack: Vec<URect>,
+    current_layer: LayerId,
+    layer_clear_colors: HashMap<LayerId, wgpu::Color>,
+    render_targets: Vec<RenderTarget>,
+    current_target: Option<WeakTextureRef>,
+    camera_bind_group_layout: BindGroupLayout,
+    viewport: Rect,
     clear_color: wgpu::Color,
     screen_clear_color: wgpu::Color,
+    letterbox_texture: Option<TextureRef>,
     last_render_at: Millis,
+    frame_delta: MillisDuration,
+    interpolation_alpha: f32,
+    fps: f32,
     scale: f32,
+    camera_zoom: f32,
+    camera_rotation: f32,
     surface_texture_format: TextureFormat,
+    virtual_texture_format: TextureFormat,
     debug_tick: u64,
+    quad_limits: QuadLimits,
+    quad_limits_exceeded_logged: bool,
+    last_frame_stats: RenderStats,
+    slow_frame_budget: Option<MillisDuration>,
+    /// When the previous [`Render::render`] was called, for the slow frame warning.
+    last_frame_at: Option<Millis>,
+    custom_passes: Vec<CustomPass>,
 }
 
 impl Render {}
@@ -196,6 +517,7 @@ impl Debug for Render {
 }
 
 impl Render {
+    /// Creates a render where the virtual surface has the same format as the surface.
     #[must_use]
     pub fn new(
         device: Arc<wgpu::Device>,
@@ -204,56 +526,211 @@ impl Render {
         physical_size: UVec2,
         virtual_surface_size: UVec2,
         now: Millis,
+    ) -> Self {
+        Self::new_with_virtual_texture_format(
+            device,
+            queue,
+            surface_texture_format,
+            surface_texture_format,
+            physical_size,
+            virtual_surface_size,
+            now,
+        )
+    }
+
+    /// Creates a render where everything is rendered to a virtual surface of
+    /// `virtual_texture_format`, e.g. `Rgba16Float` to avoid banding when accumulating light.
+    /// The virtual surface is converted to `surface_texture_format` when it is drawn to the screen.
+    #[must_use]
+    pub fn new_with_virtual_texture_format(
+        device: Arc<wgpu::Device>,
+        queue: Arc<wgpu::Queue>, // Queue to talk to device
+        surface_texture_format: wgpu::TextureFormat,
+        virtual_texture_format: wgpu::TextureFormat,
+        physical_size: UVec2,
+        virtual_surface_size: UVec2,
+        now: Millis,
     ) -> Self {
         let sprite_info = SpriteInfo::new(
             &device,
             surface_texture_format,
+            virtual_texture_format,
             create_view_uniform_view_projection_matrix(physical_size),
         );
 
         let (virtual_surface_texture, virtual_surface_texture_view, virtual_to_surface_bind_group) =
-            Self::create_virtual_texture(&device, surface_texture_format, virtual_surface_size);
+            Self::create_virtual_texture(
+                &device,
+                virtual_texture_format,
+                virtual_surface_size,
+                TextureFilter::Nearest,
+            );
+        let virtual_surface_depth_view =
+            Self::create_virtual_depth_view(&device, virtual_surface_size);
+        let trilinear_sampler = create_trilinear_sampler(&device, "sprite trilinear sampler");
+
+        let quad_limits = QuadLimits::default();
+        let mut quad_matrix_and_uv_instance_buffers = Vec::with_capacity(INSTANCE_BUFFER_COUNT);
+        if instance_capacity(&sprite_info.quad_matrix_and_uv_instance_buffer)
+            >= quad_limits.per_render
+        {
+            quad_matrix_and_uv_instance_buffers
+                .push(sprite_info.quad_matrix_and_uv_instance_buffer);
+        }
+        while quad_matrix_and_uv_instance_buffers.len() < INSTANCE_BUFFER_COUNT {
+            quad_matrix_and_uv_instance_buffers.push(create_quad_matrix_and_uv_instance_buffer(
+                &device,
+                quad_limits.per_render,
+                "sprite_instance buffer",
+            ));
+        }
+
+        let overdraw_pipeline = create_debug_shader_info(
+            &device,
+            virtual_texture_format,
+            &sprite_info.camera_bind_group_layout,
+            wgpu::PolygonMode::Fill,
+            "Debug (Overdraw)",
+        );
+        let wireframe_pipeline = device
+            .features()
+            .contains(wgpu::Features::POLYGON_MODE_LINE)
+            .then(|| {
+                create_debug_shader_info(
+                    &device,
+                    virtual_texture_format,
+                    &sprite_info.camera_bind_group_layout,
+                    wgpu::PolygonMode::Line,
+                    "Debug (Wireframe)",
+                )
+            });
 
         Self {
             device,
             queue,
             surface_texture_format,
+            virtual_texture_format,
             items: Vec::new(),
             //   fonts: Vec::new(),
             virtual_to_screen_shader_info: sprite_info.virtual_to_screen_shader_info,
+            crt_screen_shader_info: sprite_info.crt_screen_shader_info,
+            screen_effect: ScreenEffect::None,
+            screen_effect_uniform_buffer: sprite_info.screen_effect_uniform_buffer,
+            screen_effect_bind_group: sprite_info.screen_effect_bind_group,
             virtual_surface_texture,
             virtual_surface_texture_view,
+            virtual_surface_depth_view,
+            flush_depth: None,
+            depth_buffer_enabled: false,
+            half_texel_inset: false,
+            pixel_snap: false,
+            quad_antialiasing: false,
             virtual_to_surface_bind_group,
             sampler: sprite_info.sampler,
+            trilinear_sampler,
             normal_sprite_pipeline: sprite_info.sprite_shader_info,
+            multiply_sprite_pipeline: sprite_info.sprite_multiply_shader_info,
+            premultiplied_sprite_pipeline: sprite_info.sprite_premultiplied_shader_info,
+            outline_sprite_pipeline: sprite_info.sprite_outline_shader_info,
+            opaque_sprite_pipeline: sprite_info.sprite_opaque_shader_info,
             quad_shader_info: sprite_info.quad_shader_info,
+            circle_shader_info: sprite_info.circle_shader_info,
+            rounded_quad_shader_info: sprite_info.rounded_quad_shader_info,
             mask_shader_info: sprite_info.mask_shader_info,
             light_shader_info: sprite_info.light_shader_info,
+            overdraw_pipeline,
+            wireframe_pipeline,
+            debug_draw: DebugDraw::Off,
             texture_sampler_bind_group_layout: sprite_info.sprite_texture_sampler_bind_group_layout,
             index_buffer: sprite_info.index_buffer,
             vertex_buffer: sprite_info.vertex_buffer,
-            quad_matrix_and_uv_instance_buffer: sprite_info.quad_matrix_and_uv_instance_buffer,
+            quad_matrix_and_uv_instance_buffers,
+            instance_buffer_index: 0,
             camera_bind_group: sprite_info.camera_bind_group,
             batch_offsets: Vec::new(),
+            clip_stack: Vec::new(),
+            current_layer: LayerId::default(),
+            layer_clear_colors: HashMap::new(),
+            render_targets: Vec::new(),
+            current_target: None,
+            camera_bind_group_layout: sprite_info.camera_bind_group_layout,
             camera_buffer: sprite_info.camera_uniform_buffer,
-            viewport: Self::viewport_from_integer_scale(physical_size, virtual_surface_size),
+            viewport: rect_from_urect(Self::viewport_from_integer_scale(
+                physical_size,
+                virtual_surface_size,
+            )),
             clear_color: to_wgpu_color(Color::from_f32(0.008, 0.015, 0.008, 1.0)),
             screen_clear_color: to_wgpu_color(Color::from_f32(0.018, 0.025, 0.018, 1.0)),
+            letterbox_texture: None,
             origin: Vec2::new(0, 0),
+            origin_fraction: (0.0, 0.0),
             last_render_at: now,
+            frame_delta: MillisDuration::from_millis(0),
+            interpolation_alpha: 0.0,
+            fps: 0.0,
             physical_surface_size: physical_size,
+            present_mode: wgpu::PresentMode::Fifo,
+            surface_needs_configure: false,
             viewport_strategy: ViewportStrategy::FitIntegerScaling,
+            blit_filter_override: None,
+            virtual_to_surface_filter: TextureFilter::Nearest,
             virtual_surface_size,
             scale: 1.0,
+            camera_zoom: 1.0,
+            camera_rotation: 0.0,
             debug_tick: 0,
+            quad_limits,
+            quad_limits_exceeded_logged: false,
+            last_frame_stats: RenderStats::default(),
+            slow_frame_budget: None,
+            last_frame_at: None,
+            custom_passes: Vec::new(),
+        }
+    }
+
+    /// Sets how many quads that can be rendered for one item, one batch and the whole frame.
+    /// The instance buffer grows if it can not hold `per_render` quads. Quads over the limits
+    /// are dropped, see [`RenderStats::quads_dropped`].
+    ///
+    /// # Panics
+    /// If a limit is zero, or larger than the limit that contains it.
+    pub fn set_quad_limits(&mut self, quad_limits: QuadLimits) {
+        assert!(
+            quad_limits.per_render_item > 0
+                && quad_limits.per_render_item <= quad_limits.per_batch
+                && quad_limits.per_batch <= quad_limits.per_render,
+            "invalid quad limits {quad_limits:?}"
+        );
+        if instance_capacity(&self.quad_matrix_and_uv_instance_buffers[0]) < quad_limits.per_render
+        {
+            debug!(
+                capacity = quad_limits.per_render,
+                "growing the sprite instance buffers"
+            );
+            self.quad_matrix_and_uv_instance_buffers = (0..INSTANCE_BUFFER_COUNT)
+                .map(|_| {
+                    create_quad_matrix_and_uv_instance_buffer(
+                        &self.device,
+                        quad_limits.per_render,
+                        "sprite_instance buffer",
+                    )
+                })
+                .collect();
         }
+        self.quad_limits = quad_limits;
+    }
+
+    #[must_use]
+    pub const fn quad_limits(&self) -> QuadLimits {
+        self.quad_limits
     }
 
     #[must_use]
     pub fn create_virtual_texture(
         device: &Device,
-        surface_texture_format: TextureFormat,
+        virtual_texture_format: TextureFormat,
         virtual_surface_size: UVec2,
+        filter: TextureFilter,
     ) -> (wgpu::Texture, TextureView, BindGroup) {
         // Create a texture at your virtual resolution (e.g., 320x240)
         let virtual_surface_texture = device.create_texture(&wgpu::TextureDescriptor {
@@ -266,43 +743,409 @@ impl Render {
             mip_level_count: 1,
             sample_count: 1,
             dimension: wgpu::TextureDimension::D2,
-            format: surface_texture_format, // TODO: Check: Should probably always be same as swap chain format?
-            usage: wgpu::TextureUsages::RENDER_ATTACHMENT | wgpu::TextureUsages::TEXTURE_BINDING,
+            format: virtual_texture_format,
+            usage: wgpu::TextureUsages::RENDER_ATTACHMENT
+                | wgpu::TextureUsages::TEXTURE_BINDING
+                | wgpu::TextureUsages::COPY_SRC,
             view_formats: &[],
         });
 
         let virtual_surface_texture_view =
             virtual_surface_texture.create_view(&wgpu::TextureViewDescriptor::default());
 
-        let virtual_to_screen_sampler =
-            create_nearest_sampler(device, "nearest sampler for virtual to screen");
+        let virtual_to_surface_bind_group = Self::create_virtual_to_surface_bind_group(
+            device,
+            &virtual_surface_texture_view,
+            filter,
+        );
+
+        (
+            virtual_surface_texture,
+            virtual_surface_texture_view,
+            virtual_to_surface_bind_group,
+        )
+    }
+
+    fn create_virtual_to_surface_bind_group(
+        device: &Device,
+        virtual_surface_texture_view: &TextureView,
+        filter: TextureFilter,
+    ) -> BindGroup {
+        let virtual_to_screen_sampler = match filter {
+            TextureFilter::Nearest => {
+                create_nearest_sampler(device, "nearest sampler for virtual to screen")
+            }
+            TextureFilter::Linear => {
+                create_linear_clamp_sampler(device, "linear sampler for virtual to screen")
+            }
+        };
         let virtual_to_screen_layout =
             create_texture_and_sampler_group_layout(device, "virtual to screen layout");
-        let virtual_to_surface_bind_group = create_texture_and_sampler_bind_group_ex(
+        create_texture_and_sampler_bind_group_ex(
             device,
             &virtual_to_screen_layout,
-            &virtual_surface_texture_view,
+            virtual_surface_texture_view,
             &virtual_to_screen_sampler,
             "virtual to screen bind group",
+        )
+    }
+
+    /// Overrides how the virtual surface is filtered when it is scaled to the window.
+    /// With `None`, it is [`TextureFilter::Linear`] for [`ViewportStrategy::FitFloatScaling`],
+    /// where the scale is not a whole number, and [`TextureFilter::Nearest`] otherwise.
+    pub fn set_blit_filter(&mut self, filter: Option<TextureFilter>) {
+        self.blit_filter_override = filter;
+        self.update_virtual_to_surface_filter();
+    }
+
+    #[must_use]
+    pub const fn blit_filter(&self) -> TextureFilter {
+        if let Some(filter) = self.blit_filter_override {
+            return filter;
+        }
+        match self.viewport_strategy {
+            ViewportStrategy::FitFloatScaling => TextureFilter::Linear,
+            ViewportStrategy::FitIntegerScaling
+            | ViewportStrategy::FillCropInteger
+            | ViewportStrategy::MatchPhysicalSize => TextureFilter::Nearest,
+        }
+    }
+
+    fn update_virtual_to_surface_filter(&mut self) {
+        let filter = self.blit_filter();
+        if filter == self.virtual_to_surface_filter {
+            return;
+        }
+        self.virtual_to_surface_filter = filter;
+        self.virtual_to_surface_bind_group = Self::create_virtual_to_surface_bind_group(
+            &self.device,
+            &self.virtual_surface_texture_view,
+            filter,
+        );
+    }
+
+    fn create_virtual_depth_view(device: &Device, virtual_surface_size: UVec2) -> TextureView {
+        create_depth_texture_view(
+            device,
+            wgpu::Extent3d {
+                width: u32::from(virtual_surface_size.x),
+                height: u32::from(virtual_surface_size.y),
+                depth_or_array_layers: 1,
+            },
+            "Render Depth Texture",
+        )
+    }
+
+    /// Enables the depth buffer, so that `position.z` is also written as depth.
+    ///
+    /// Opaque sprites ([`BlendMode::Opaque`]) are then drawn first, only sorted on material,
+    /// and the GPU discards the pixels that are covered by something with a higher z.
+    /// Blended content still needs to be sorted on z and is drawn afterwards, tested against the
+    /// depth of the opaque sprites.
+    pub const fn set_depth_buffer_enabled(&mut self, enabled: bool) {
+        self.depth_buffer_enabled = enabled;
+    }
+
+    #[must_use]
+    pub const fn depth_buffer_enabled(&self) -> bool {
+        self.depth_buffer_enabled
+    }
+
+    /// Shrinks the texture rect of sprites, glyphs and tiles by half a texel on each side.
+    ///
+    /// Prevents bleeding from neighboring atlas frames when the scale is not an integer,
+    /// at the cost of cutting off half of the outermost texels.
+    pub const fn set_half_texel_inset(&mut self, enabled: bool) {
+        self.half_texel_inset = enabled;
+    }
+
+    #[must_use]
+    pub const fn half_texel_inset(&self) -> bool {
+        self.half_texel_inset
+    }
+
+    /// Rounds the position of every quad and the camera translation to whole virtual pixels,
+    /// so pixel art does not jitter when the camera zooms or items have float scales and pivots.
+    /// The virtual surface is scaled to the window afterwards, so it works with all
+    /// viewport strategies.
+    pub const fn set_pixel_snap(&mut self, enabled: bool) {
+        self.pixel_snap = enabled;
+    }
+
+    #[must_use]
+    pub const fn pixel_snap(&self) -> bool {
+        self.pixel_snap
+    }
+
+    /// Smooths the edges of quads, gradients and lines, which are jagged when they are rotated
+    /// or float scaled. The outermost pixel of each edge is faded out in the quad shader,
+    /// so the quads look half a pixel smaller.
+    pub const fn set_quad_antialiasing(&mut self, enabled: bool) {
+        self.quad_antialiasing = enabled;
+    }
+
+    #[must_use]
+    pub const fn quad_antialiasing(&self) -> bool {
+        self.quad_antialiasing
+    }
+
+    /// Draws all quads with a debug pipeline instead of their material, see [`DebugDraw`].
+    /// Falls back to [`DebugDraw::Overdraw`] if wireframe is not supported by the device.
+    pub fn set_debug_draw(&mut self, debug_draw: DebugDraw) {
+        self.debug_draw = if debug_draw == DebugDraw::Wireframe && self.wireframe_pipeline.is_none()
+        {
+            warn!("wireframe requires the POLYGON_MODE_LINE device feature, using overdraw");
+            DebugDraw::Overdraw
+        } else {
+            debug_draw
+        };
+    }
+
+    #[must_use]
+    pub const fn debug_draw(&self) -> DebugDraw {
+        self.debug_draw
+    }
+
+    fn debug_pipeline(&self) -> Option<&RenderPipeline> {
+        match self.debug_draw {
+            DebugDraw::Off => None,
+            DebugDraw::Overdraw => Some(&self.overdraw_pipeline.pipeline),
+            DebugDraw::Wireframe => self
+                .wireframe_pipeline
+                .as_ref()
+                .map(|wireframe| &wireframe.pipeline),
+        }
+    }
+
+    /// Reads back the virtual surface texture as tightly packed RGBA8 pixels, top row first.
+    ///
+    /// Blocks until the GPU has finished the copy, so it is intended for tooling
+    /// (screenshots, visual regression tests) and not for every frame.
+    ///
+    /// `Rgba16Float` surfaces are clamped to `0.0..=1.0` and converted to RGBA8.
+    ///
+    /// # Panics
+    ///
+    /// If the virtual surface format is not an RGBA8, BGRA8 or `Rgba16Float` format,
+    /// or if the readback buffer could not be mapped.
+    #[must_use]
+    pub fn capture_virtual_texture(&self) -> (UVec2, Vec<u8>) {
+        let width = u32::from(self.virtual_surface_size.x);
+        let height = u32::from(self.virtual_surface_size.y);
+
+        let format = self.virtual_texture_format;
+        let is_bgra = matches!(
+            format,
+            TextureFormat::Bgra8Unorm | TextureFormat::Bgra8UnormSrgb
         );
+        let is_half_float = format == TextureFormat::Rgba16Float;
+        assert!(
+            is_bgra
+                || is_half_float
+                || matches!(
+                    format,
+                    TextureFormat::Rgba8Unorm | TextureFormat::Rgba8UnormSrgb
+                ),
+            "capture does not support the virtual texture format {format:?}"
+        );
+        let bytes_per_pixel = if is_half_float { 8 } else { 4 };
+
+        let unpadded_bytes_per_row = width * bytes_per_pixel;
+        // wgpu requires each row in the buffer to be aligned to 256 octets
+        let padded_bytes_per_row = unpadded_bytes_per_row
+            .div_ceil(wgpu::COPY_BYTES_PER_ROW_ALIGNMENT)
+            * wgpu::COPY_BYTES_PER_ROW_ALIGNMENT;
+
+        let readback_buffer = self.device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("virtual texture readback buffer"),
+            size: u64::from(padded_bytes_per_row * height),
+            usage: wgpu::BufferUsages::COPY_DST | wgpu::BufferUsages::MAP_READ,
+            mapped_at_creation: false,
+        });
+
+        let mut command_encoder =
+            self.device
+                .create_command_encoder(&wgpu::CommandEncoderDescriptor {
+                    label: Some("virtual texture capture encoder"),
+                });
+
+        command_encoder.copy_texture_to_buffer(
+            self.virtual_surface_texture.as_image_copy(),
+            wgpu::TexelCopyBufferInfo {
+                buffer: &readback_buffer,
+                layout: wgpu::TexelCopyBufferLayout {
+                    offset: 0,
+                    bytes_per_row: Some(padded_bytes_per_row),
+                    rows_per_image: Some(height),
+                },
+            },
+            wgpu::Extent3d {
+                width,
+                height,
+                depth_or_array_layers: 1,
+            },
+        );
+
+        self.queue.submit(Some(command_encoder.finish()));
+
+        let buffer_slice = readback_buffer.slice(..);
+        let (sender, receiver) = std::sync::mpsc::channel();
+        buffer_slice.map_async(wgpu::MapMode::Read, move |result| {
+            let _ = sender.send(result);
+        });
+        self.device
+            .poll(wgpu::PollType::wait_indefinitely())
+            .expect("device poll failed while capturing virtual texture");
+        receiver
+            .recv()
+            .expect("map callback was never called")
+            .expect("could not map readback buffer");
+
+        let mut pixels = Vec::with_capacity((unpadded_bytes_per_row * height) as usize);
+        {
+            let padded_data = buffer_slice.get_mapped_range();
+            for row in padded_data.chunks_exact(padded_bytes_per_row as usize) {
+                pixels.extend_from_slice(&row[..unpadded_bytes_per_row as usize]);
+            }
+        }
+        readback_buffer.unmap();
+
+        if is_bgra {
+            for pixel in pixels.chunks_exact_mut(4) {
+                pixel.swap(0, 2);
+            }
+        } else if is_half_float {
+            pixels = pixels
+                .chunks_exact(2)
+                .map(|channel| {
+                    let value = f16_to_f32(u16::from_le_bytes([channel[0], channel[1]]));
+                    (value.clamp(0.0, 1.0) * 255.0).round() as u8
+                })
+                .collect();
+        }
+
+        (self.virtual_surface_size, pixels)
+    }
+
+    pub fn set_now(&mut self, now: Millis) {
+        if let Some(delta) = now.checked_duration_since_ms(self.last_render_at)
+            && delta.as_millis() > 0
+        {
+            self.frame_delta = delta;
+            // Smoothed, so it is readable as an on-screen counter
+            let current_fps = 1000.0 / delta.as_millis() as f32;
+            self.fps = if self.fps == 0.0 {
+                current_fps
+            } else {
+                self.fps.mul_add(0.9, current_fps * 0.1)
+            };
+        }
+        self.last_render_at = now;
+    }
+
+    /// Time between the two latest rendered frames.
+    #[must_use]
+    pub const fn frame_delta(&self) -> MillisDuration {
+        self.frame_delta
+    }
+
+    /// How far the frame is between the latest fixed tick and the next, from `0.0` to `1.0`.
+    pub const fn set_interpolation_alpha(&mut self, alpha: f32) {
+        self.interpolation_alpha = alpha;
+    }
+
+    #[must_use]
+    pub const fn interpolation_alpha(&self) -> f32 {
+        self.interpolation_alpha
+    }
+
+    /// Batches, quads and draw calls of the latest rendered frame, e.g. to find out why
+    /// a frame is split into many batches.
+    #[must_use]
+    pub const fn last_frame_stats(&self) -> RenderStats {
+        self.last_frame_stats
+    }
+
+    /// Logs a warning, with the stats of the latest frame, when the time between two
+    /// [`Self::render`] calls is longer than `budget`, e.g. 20 ms to catch hitches.
+    /// `None` (the default) disables the warning.
+    pub const fn set_slow_frame_budget(&mut self, budget: Option<MillisDuration>) {
+        self.slow_frame_budget = budget;
+    }
+
+    #[must_use]
+    pub const fn slow_frame_budget(&self) -> Option<MillisDuration> {
+        self.slow_frame_budget
+    }
+
+    fn warn_if_slow_frame(&mut self, now: Millis) {
+        if let (Some(budget), Some(last_frame_at)) = (self.slow_frame_budget, self.last_frame_at)
+            && let Some(frame_time) = now.checked_duration_since_ms(last_frame_at)
+            && frame_time > budget
+        {
+            let stats = self.last_frame_stats;
+            warn!(
+                frame_time_ms = frame_time.as_millis(),
+                budget_ms = budget.as_millis(),
+                batches = stats.batches,
+                quads = stats.quads,
+                draw_calls = stats.draw_calls,
+                "slow frame"
+            );
+        }
+        self.last_frame_at = Some(now);
+    }
+
+    /// Frames per second, smoothed over the latest frames.
+    #[must_use]
+    pub const fn fps(&self) -> f32 {
+        self.fps
+    }
 
-        (
-            virtual_surface_texture,
-            virtual_surface_texture_view,
-            virtual_to_surface_bind_group,
-        )
+    /// Changes how the virtual surface is fitted into the window. Takes effect immediately,
+    /// so the renderer does not need to be recreated.
+    pub fn set_viewport_strategy(&mut self, viewport_strategy: ViewportStrategy) {
+        self.viewport_strategy = viewport_strategy;
+        self.update_viewport();
+        self.update_virtual_to_surface_filter();
     }
 
-    pub const fn set_now(&mut self, now: Millis) {
-        self.last_render_at = now;
+    #[must_use]
+    pub const fn viewport_strategy(&self) -> &ViewportStrategy {
+        &self.viewport_strategy
+    }
+
+    fn update_viewport(&mut self) {
+        self.viewport = match self.viewport_strategy {
+            ViewportStrategy::FitIntegerScaling => {
+                rect_from_urect(Self::viewport_from_integer_scale(
+                    self.physical_surface_size,
+                    self.virtual_surface_size,
+                ))
+            }
+            ViewportStrategy::FitFloatScaling => rect_from_urect(Self::viewport_from_float_scale(
+                self.physical_surface_size,
+                self.virtual_surface_size,
+            )),
+            ViewportStrategy::FillCropInteger => Self::viewport_from_integer_fill_scale(
+                self.physical_surface_size,
+                self.virtual_surface_size,
+            ),
+            ViewportStrategy::MatchPhysicalSize => Rect::new(
+                0,
+                0,
+                self.physical_surface_size.x,
+                self.physical_surface_size.y,
+            ),
+        };
     }
 
     #[must_use]
     pub const fn virtual_surface_size_with_scaling(&self) -> UVec2 {
         match self.viewport_strategy {
-            ViewportStrategy::FitIntegerScaling | ViewportStrategy::FitFloatScaling => {
-                self.virtual_surface_size
-            }
+            ViewportStrategy::FitIntegerScaling
+            | ViewportStrategy::FitFloatScaling
+            | ViewportStrategy::FillCropInteger => self.virtual_surface_size,
             ViewportStrategy::MatchPhysicalSize => self.physical_surface_size,
         }
     }
@@ -312,16 +1155,67 @@ impl Render {
         self.physical_surface_size
     }
 
+    /// Where the virtual surface is drawn on the physical surface. With
+    /// [`ViewportStrategy::FillCropInteger`] the position can be negative and the size larger
+    /// than the physical surface.
     #[must_use]
-    pub const fn viewport(&self) -> URect {
+    pub const fn viewport(&self) -> Rect {
         self.viewport
     }
 
+    /// The part of the virtual surface that is visible in the window, in virtual coordinates.
+    /// It is the whole [`Self::virtual_surface_size_with_scaling`], except with
+    /// [`ViewportStrategy::FillCropInteger`], where the edges can be cropped.
+    #[must_use]
+    pub fn virtual_bounds(&self) -> URect {
+        let virtual_size = self.virtual_surface_size_with_scaling();
+        let viewport = self.viewport;
+        if viewport.size.x == 0 || viewport.size.y == 0 {
+            return URect::new(0, 0, virtual_size.x, virtual_size.y);
+        }
+
+        // Maps a physical coordinate to the virtual surface, clamped to its edges
+        let to_virtual = |physical: u16, viewport_position: i16, viewport_size: u16, size: u16| {
+            let relative = i32::from(physical) - i32::from(viewport_position);
+            (relative * i32::from(size) / i32::from(viewport_size)).clamp(0, i32::from(size)) as u16
+        };
+        let physical = self.physical_surface_size;
+        let min_x = to_virtual(0, viewport.position.x, viewport.size.x, virtual_size.x);
+        let min_y = to_virtual(0, viewport.position.y, viewport.size.y, virtual_size.y);
+        let max_x = to_virtual(
+            physical.x,
+            viewport.position.x,
+            viewport.size.x,
+            virtual_size.x,
+        );
+        let max_y = to_virtual(
+            physical.y,
+            viewport.position.y,
+            viewport.size.y,
+            virtual_size.y,
+        );
+
+        URect::new(min_x, min_y, max_x - min_x, max_y - min_y)
+    }
+
+    /// If a virtual position, e.g. the cursor, is on the visible part of the virtual surface.
+    #[must_use]
+    pub fn is_in_virtual_bounds(&self, position: UVec2) -> bool {
+        let bounds = self.virtual_bounds();
+        position.x >= bounds.position.x
+            && position.y >= bounds.position.y
+            && position.x < bounds.position.x + bounds.size.x
+            && position.y < bounds.position.y + bounds.size.y
+    }
+
     #[inline]
     fn push_sprite(&mut self, position: Vec3, material: &MaterialRef, sprite: Sprite) {
         self.items.push(RenderItem {
             position,
             material_ref: material.clone(),
+            clip: self.current_clip(),
+            layer: self.current_layer,
+            target: self.current_target,
             renderable: Renderable::Sprite(sprite),
         });
     }
@@ -332,11 +1226,27 @@ impl Render {
         size: UVec2,
         color: Color,
         alpha_masked: &MaterialRef,
+    ) {
+        self.push_mask_with_offset(position, size, Vec2::new(0, 0), color, alpha_masked);
+    }
+
+    /// Same as [`Self::push_mask`], but the alpha mask is sampled `mask_offset` texels away,
+    /// so the mask can be scrolled independently of the diffuse texture.
+    pub fn push_mask_with_offset(
+        &mut self,
+        position: Vec3,
+        size: UVec2,
+        mask_offset: Vec2,
+        color: Color,
+        alpha_masked: &MaterialRef,
     ) {
         self.items.push(RenderItem {
             position,
             material_ref: alpha_masked.clone(),
-            renderable: Renderable::Mask(size, color),
+            clip: self.current_clip(),
+            layer: self.current_layer,
+            target: self.current_target,
+            renderable: Renderable::Mask(size, mask_offset, color),
         });
     }
 
@@ -346,6 +1256,7 @@ impl Render {
         primary_texture: TextureRef,
         alpha_texture: TextureRef,
         texture_offset: UVec2,
+        mask_offset: Vec2,
         color: Color,
     ) {
         let masked_material = Material {
@@ -361,7 +1272,10 @@ impl Render {
         self.items.push(RenderItem {
             position,
             material_ref: masked_material_ref,
-            renderable: Renderable::Mask(texture_offset, color),
+            clip: self.current_clip(),
+            layer: self.current_layer,
+            target: self.current_target,
+            renderable: Renderable::Mask(texture_offset, mask_offset, color),
         });
     }
 
@@ -378,11 +1292,16 @@ impl Render {
             color,
             origin_in_atlas: UVec2::new(0, 0),
             size_inside_atlas: None,
+            mode: nine_slice_and_material.mode,
+            draw_center: nine_slice_and_material.draw_center,
         };
 
         self.items.push(RenderItem {
             position,
             material_ref: nine_slice_and_material.material_ref.clone(),
+            clip: self.current_clip(),
+            layer: self.current_layer,
+            target: self.current_target,
             renderable: Renderable::NineSlice(nine_slice_info),
         });
     }
@@ -400,13 +1319,230 @@ impl Render {
             color,
             origin_in_atlas: UVec2::new(0, 0),
             size_inside_atlas: None,
+            mode: NineSliceMode::Stretch,
+            draw_center: nine_slice_and_material.draw_center,
         };
 
         self.items.push(RenderItem {
             position,
             material_ref: nine_slice_and_material.material_ref.clone(),
-            renderable: Renderable::NineSliceStretch(nine_slice_info),
+            clip: self.current_clip(),
+            layer: self.current_layer,
+            target: self.current_target,
+            renderable: Renderable::NineSlice(nine_slice_info),
+        });
+    }
+
+    /// Sets the lower left corner of the view in world coordinates.
+    pub const fn set_origin(&mut self, origin: Vec2) {
+        self.origin = origin;
+        self.origin_fraction = (0.0, 0.0);
+    }
+
+    /// Same as [`Self::set_origin`], but with a fractional origin for smooth scrolling, e.g. of
+    /// parallax layers. Item positions are still whole pixels, only the view projection is
+    /// offset by the fraction. Combine with [`Self::set_pixel_snap`] to get crisp pixels instead.
+    pub fn set_origin_f32(&mut self, x: f32, y: f32) {
+        let (whole_x, whole_y) = (x.floor(), y.floor());
+        self.origin = Vec2::new(whole_x as i16, whole_y as i16);
+        self.origin_fraction = (x - whole_x, y - whole_y);
+    }
+
+    /// Places the camera so that `center` (in world coordinates) is in the middle of the
+    /// virtual surface, zoomed by `zoom` and rotated counter-clockwise by `rotation_radians`
+    /// around that point.
+    pub fn set_camera(&mut self, center: Vec2, zoom: f32, rotation_radians: f32) {
+        let half_size = Vec2::new(
+            (self.virtual_surface_size.x / 2) as i16,
+            (self.virtual_surface_size.y / 2) as i16,
+        );
+        self.origin = center - half_size;
+        self.origin_fraction = (0.0, 0.0);
+        self.camera_zoom = zoom;
+        self.camera_rotation = rotation_radians;
+    }
+
+    /// Converts a virtual surface position, with the origin in the upper left corner
+    /// (as reported for the cursor), to a world position by applying the inverse of the
+    /// view matrix, that is the origin, scale, zoom and rotation.
+    #[must_use]
+    pub fn virtual_to_world(&self, virtual_position: UVec2) -> Vec2 {
+        let half_x = f32::from(self.virtual_surface_size.x) / 2.0;
+        let half_y = f32::from(self.virtual_surface_size.y) / 2.0;
+
+        // World Y goes up, so flip the Y-axis
+        let x = f32::from(virtual_position.x) - half_x;
+        let y = f32::from(self.virtual_surface_size.y) - f32::from(virtual_position.y) - half_y;
+
+        // `scale` is applied in clip space, which is centered as well, so it combines with the zoom
+        let total_zoom = self.scale * self.camera_zoom;
+        let zoom = if total_zoom.abs() < f32::EPSILON {
+            1.0
+        } else {
+            total_zoom
+        };
+        let (x, y) = (x / zoom, y / zoom);
+
+        let (sin, cos) = self.camera_rotation.sin_cos();
+        let rotated_x = x * cos - y * sin;
+        let rotated_y = x * sin + y * cos;
+
+        Vec2::new(
+            (rotated_x + half_x + self.origin_fraction.0).round() as i16 + self.origin.x,
+            (rotated_y + half_y + self.origin_fraction.1).round() as i16 + self.origin.y,
+        )
+    }
+
+    /// Returns the part of the world that is visible on the virtual surface with the current camera.
+    /// With rotation it is the bounding box of the rotated view.
+    #[must_use]
+    pub fn camera_world_rect(&self) -> Rect {
+        let size = self.virtual_surface_size;
+        let corners = [
+            self.virtual_to_world(UVec2::new(0, 0)),
+            self.virtual_to_world(UVec2::new(size.x, 0)),
+            self.virtual_to_world(UVec2::new(0, size.y)),
+            self.virtual_to_world(size),
+        ];
+        let min_x = corners.iter().map(|corner| corner.x).min().unwrap() - 1;
+        let min_y = corners.iter().map(|corner| corner.y).min().unwrap() - 1;
+        let max_x = corners.iter().map(|corner| corner.x).max().unwrap() + 1;
+        let max_y = corners.iter().map(|corner| corner.y).max().unwrap() + 1;
+
+        Rect::new(min_x, min_y, (max_x - min_x) as u16, (max_y - min_y) as u16)
+    }
+
+    /// Pushes a clip rectangle, in virtual surface pixels with the origin in the lower left corner.
+    /// All items pushed until the matching [`Self::pop_clip_rect`] are clipped to it.
+    /// Nested clip rectangles are intersected with the enclosing ones.
+    pub fn push_clip_rect(&mut self, rect: URect) {
+        let clip = self
+            .current_clip()
+            .map_or(rect, |current| intersect_rects(current, rect));
+        self.clip_stack.push(clip);
+    }
+
+    pub fn pop_clip_rect(&mut self) {
+        self.clip_stack.pop();
+    }
+
+    #[must_use]
+    fn current_clip(&self) -> Option<URect> {
+        self.clip_stack.last().copied()
+    }
+
+    /// Sets the layer for all items pushed from now on, until it is set again.
+    ///
+    /// Layers are rendered in order, lowest first, each in its own render pass with its own
+    /// sorting and depth buffer. So an item on a higher layer, e.g. UI, is always drawn on top of
+    /// the items on lower layers, regardless of z.
+    pub const fn set_layer(&mut self, layer: LayerId) {
+        self.current_layer = layer;
+    }
+
+    #[must_use]
+    pub const fn layer(&self) -> LayerId {
+        self.current_layer
+    }
+
+    /// Clears the target to `color` before `layer` is drawn, hiding the layers below it, e.g. for
+    /// a fullscreen menu. `None` (the default) draws the layer on top of the layers below it.
+    /// Only applied when the layer has items this frame.
+    pub fn set_layer_clear(&mut self, layer: LayerId, color: Option<Color>) {
+        match color {
+            Some(color) => {
+                self.layer_clear_colors.insert(layer, to_wgpu_color(color));
+            }
+            None => {
+                self.layer_clear_colors.remove(&layer);
+            }
+        }
+    }
+
+    #[must_use]
+    pub fn layer_clear(&self, layer: LayerId) -> Option<wgpu::Color> {
+        self.layer_clear_colors.get(&layer).copied()
+    }
+
+    /// Reserves room for at least `additional` more items this frame. The items are cleared
+    /// but keep their capacity between frames, so this is mostly for the first frame or a
+    /// known spike, e.g. a burst of particles.
+    pub fn reserve(&mut self, additional: usize) {
+        self.items.reserve(additional);
+    }
+
+    /// Creates an offscreen texture of `size` that items can be rendered to,
+    /// see [`Self::set_render_target`].
+    ///
+    /// The returned [`Texture`] should be inserted into the texture assets with `texture_ref`,
+    /// so that the target can be drawn as a normal sprite. Render targets are rendered before
+    /// the virtual surface each frame, with their own camera that has the origin in the lower
+    /// left corner of the target.
+    pub fn create_render_target(&mut self, texture_ref: &TextureRef, size: UVec2) -> Texture {
+        let extent = wgpu::Extent3d {
+            width: u32::from(size.x),
+            height: u32::from(size.y),
+            depth_or_array_layers: 1,
+        };
+        let texture = self.device.create_texture(&wgpu::TextureDescriptor {
+            label: Some("Render Target Texture"),
+            size: extent,
+            mip_level_count: 1,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            format: self.virtual_texture_format,
+            usage: wgpu::TextureUsages::RENDER_ATTACHMENT | wgpu::TextureUsages::TEXTURE_BINDING,
+            view_formats: &[],
+        });
+        let view = texture.create_view(&wgpu::TextureViewDescriptor::default());
+        let depth_view = create_depth_texture_view(&self.device, extent, "Render Target Depth");
+
+        let camera_buffer = create_camera_uniform_buffer(
+            &self.device,
+            create_view_projection_matrix_from_virtual(size.x, size.y),
+            "render target camera",
+        );
+        let camera_bind_group = create_camera_uniform_bind_group(
+            &self.device,
+            &self.camera_bind_group_layout,
+            &camera_buffer,
+            "render target camera",
+        );
+
+        self.render_targets.push(RenderTarget {
+            texture_ref: texture_ref.into(),
+            view,
+            depth_view,
+            size,
+            camera_buffer,
+            camera_bind_group,
         });
+
+        self.texture_resource_from_texture(&texture, "render target")
+    }
+
+    /// Directs all items pushed from now on to the render target created for `target`
+    /// with [`Self::create_render_target`], or to the virtual surface if `None`.
+    pub fn set_render_target(&mut self, target: Option<&TextureRef>) {
+        self.current_target = target.map(WeakTextureRef::from);
+    }
+
+    /// Converts a lower left origin clip rectangle to a upper left origin scissor rectangle,
+    /// clamped to the target size. `None` if nothing of the clip rectangle is inside the target.
+    fn scissor_from_clip(clip: URect, target_size: UVec2) -> Option<(u32, u32, u32, u32)> {
+        let clamped = intersect_rects(clip, URect::new(0, 0, target_size.x, target_size.y));
+        if clamped.size.x == 0 || clamped.size.y == 0 {
+            return None;
+        }
+        // The clamped rectangle is inside the target, so this can not underflow
+        let y_from_top =
+            u32::from(target_size.y) - u32::from(clamped.position.y) - u32::from(clamped.size.y);
+        Some((
+            u32::from(clamped.position.x),
+            y_from_top,
+            u32::from(clamped.size.x),
+            u32::from(clamped.size.y),
+        ))
     }
 
     #[must_use]
@@ -436,6 +1572,25 @@ impl Render {
         )
     }
 
+    /// Scales up until the whole physical surface is covered, and centers the result.
+    #[must_use]
+    pub fn viewport_from_integer_fill_scale(physical_size: UVec2, virtual_size: UVec2) -> Rect {
+        let scale_factor = physical_size
+            .x
+            .div_ceil(virtual_size.x)
+            .max(physical_size.y.div_ceil(virtual_size.y))
+            .max(1);
+
+        let viewport_size = virtual_size * scale_factor;
+
+        Rect::new(
+            ((i32::from(physical_size.x) - i32::from(viewport_size.x)) / 2) as i16,
+            ((i32::from(physical_size.y) - i32::from(viewport_size.y)) / 2) as i16,
+            viewport_size.x,
+            viewport_size.y,
+        )
+    }
+
     #[must_use]
     pub fn viewport_from_float_scale(physical_size: UVec2, virtual_size: UVec2) -> URect {
         let window_aspect = f32::from(physical_size.x) / f32::from(physical_size.y);
@@ -472,8 +1627,37 @@ impl Render {
         )
     }
 
-    pub const fn resize(&mut self, physical_size: UVec2) {
+    pub fn resize(&mut self, physical_size: UVec2) {
         self.physical_surface_size = physical_size;
+        // The window configures the surface with `Fifo` on resize
+        if self.present_mode != wgpu::PresentMode::Fifo {
+            self.surface_needs_configure = true;
+        }
+    }
+
+    /// Requests a present mode for the window surface, e.g. `Immediate` for an uncapped
+    /// frame rate. The surface is configured again before the next frame. If the mode is
+    /// not supported, the closest supported mode is used, falling back to `Fifo` (vsync).
+    pub fn set_present_mode(&mut self, present_mode: wgpu::PresentMode) {
+        if present_mode != self.present_mode {
+            self.present_mode = present_mode;
+            self.surface_needs_configure = true;
+        }
+    }
+
+    #[must_use]
+    pub const fn present_mode(&self) -> wgpu::PresentMode {
+        self.present_mode
+    }
+
+    /// Returns the requested present mode if the surface needs to be configured again.
+    pub(crate) const fn take_surface_configure(&mut self) -> Option<wgpu::PresentMode> {
+        if self.surface_needs_configure {
+            self.surface_needs_configure = false;
+            Some(self.present_mode)
+        } else {
+            None
+        }
     }
 
     pub fn resize_virtual(&mut self, virtual_surface_size: UVec2) {
@@ -485,12 +1669,15 @@ impl Render {
         let (virtual_surface_texture, virtual_surface_texture_view, virtual_to_surface_bind_group) =
             Self::create_virtual_texture(
                 &self.device,
-                self.surface_texture_format,
+                self.virtual_texture_format,
                 virtual_surface_size,
+                self.virtual_to_surface_filter,
             );
         self.virtual_surface_texture = virtual_surface_texture;
         self.virtual_surface_texture_view = virtual_surface_texture_view;
         self.virtual_to_surface_bind_group = virtual_to_surface_bind_group;
+        self.virtual_surface_depth_view =
+            Self::create_virtual_depth_view(&self.device, virtual_surface_size);
     }
 
     pub fn sprite_atlas(&mut self, position: Vec3, atlas_rect: URect, material_ref: &MaterialRef) {
@@ -522,6 +1709,16 @@ impl Render {
         );
     }
 
+    pub fn draw_animation(
+        &mut self,
+        position: Vec3,
+        atlas: &impl FrameLookup,
+        animation: &AnimationState,
+        now: Millis,
+    ) {
+        self.sprite_atlas_frame(position, animation.frame(now), atlas);
+    }
+
     pub fn sprite_atlas_frame_ex(
         &mut self,
         position: Vec3,
@@ -549,6 +1746,27 @@ impl Render {
         self.push_sprite(position, material, Sprite { params });
     }
 
+    /// Draws the sprite with a one texel `outline_color` border around its opaque texels.
+    /// The outline is drawn inside the sprite rect, so it needs a transparent border.
+    pub fn draw_sprite_outlined(
+        &mut self,
+        position: Vec3,
+        material: &MaterialRef,
+        mut params: SpriteParams,
+        outline_color: Color,
+    ) {
+        let Some(primary_texture) = material.primary_texture() else {
+            warn!(%material, "can not outline a material without a texture");
+            return;
+        };
+        let outline_material = Arc::new(Material {
+            base: MaterialBase {},
+            kind: MaterialKind::Outline { primary_texture },
+        });
+        params.outline_color = outline_color;
+        self.push_sprite(position, &outline_material, Sprite { params });
+    }
+
     pub fn nine_slice(
         &mut self,
         position: Vec3,
@@ -578,6 +1796,9 @@ impl Render {
         self.items.push(RenderItem {
             position,
             material_ref: MaterialRef::from(material),
+            clip: self.current_clip(),
+            layer: self.current_layer,
+            target: self.current_target,
             renderable: Renderable::QuadColor(QuadColor {
                 size,
                 color,
@@ -595,6 +1816,9 @@ impl Render {
         self.items.push(RenderItem {
             position,
             material_ref: MaterialRef::from(material),
+            clip: self.current_clip(),
+            layer: self.current_layer,
+            target: self.current_target,
             renderable: Renderable::QuadColor(QuadColor {
                 size,
                 color,
@@ -603,60 +1827,325 @@ impl Render {
         });
     }
 
-    #[allow(clippy::too_many_arguments)]
-    pub fn draw_nine_slice(
+    /// Draws a quad that fades from `bottom_color` at the bottom edge to `top_color` at the top edge.
+    pub fn draw_quad_gradient(
+        &mut self,
+        position: Vec3,
+        size: UVec2,
+        top_color: Color,
+        bottom_color: Color,
+    ) {
+        self.push_quad_gradient(
+            position,
+            QuadGradient {
+                size,
+                start_color: bottom_color,
+                end_color: top_color,
+                direction: GradientDirection::Vertical,
+            },
+        );
+    }
+
+    /// Draws a quad that fades from `left_color` at the left edge to `right_color` at the right edge.
+    pub fn draw_quad_gradient_horizontal(
+        &mut self,
+        position: Vec3,
+        size: UVec2,
+        left_color: Color,
+        right_color: Color,
+    ) {
+        self.push_quad_gradient(
+            position,
+            QuadGradient {
+                size,
+                start_color: left_color,
+                end_color: right_color,
+                direction: GradientDirection::Horizontal,
+            },
+        );
+    }
+
+    fn push_quad_gradient(&mut self, position: Vec3, gradient: QuadGradient) {
+        let material = Material {
+            base: MaterialBase {},
+            kind: MaterialKind::Quad,
+        };
+
+        self.items.push(RenderItem {
+            position,
+            material_ref: MaterialRef::from(material),
+            clip: self.current_clip(),
+            layer: self.current_layer,
+            target: self.current_target,
+            renderable: Renderable::QuadGradient(gradient),
+        });
+    }
+
+    /// Draws a filled circle, `position` is the center of the circle.
+    pub fn draw_circle(&mut self, position: Vec3, radius: u16, color: Color) {
+        self.draw_ellipse(position, UVec2::new(radius, radius), color);
+    }
+
+    /// Draws a filled ellipse, `position` is the center of the ellipse.
+    pub fn draw_ellipse(&mut self, position: Vec3, radii: UVec2, color: Color) {
+        let material = Material {
+            base: MaterialBase {},
+            kind: MaterialKind::Circle,
+        };
+
+        self.items.push(RenderItem {
+            position,
+            material_ref: MaterialRef::from(material),
+            clip: self.current_clip(),
+            layer: self.current_layer,
+            target: self.current_target,
+            renderable: Renderable::Circle(Circle { radii, color }),
+        });
+    }
+
+    /// Draws a filled rectangle with corners rounded by `radius` pixels and antialiased edges.
+    /// `position` is the lower left corner. The radius is limited to half of the smallest side.
+    pub fn draw_rounded_quad(&mut self, position: Vec3, size: UVec2, radius: u16, color: Color) {
+        let material = Material {
+            base: MaterialBase {},
+            kind: MaterialKind::RoundedQuad,
+        };
+
+        self.items.push(RenderItem {
+            position,
+            material_ref: MaterialRef::from(material),
+            clip: self.current_clip(),
+            layer: self.current_layer,
+            target: self.current_target,
+            renderable: Renderable::RoundedQuad(RoundedQuad {
+                size,
+                radius,
+                color,
+            }),
+        });
+    }
+
+    /// Draws connected line segments through all `points`, see [`Self::draw_line`].
+    /// The segments end straight at the points (butt joins) and are not extended past them,
+    /// so thick lines only overlap a little on the inside of corners and get a notch on the
+    /// outside.
+    pub fn draw_polyline(&mut self, points: &[Vec2], thickness: u16, color: Color, z: i16) {
+        for segment in points.windows(2) {
+            self.draw_line(segment[0], segment[1], thickness, color, z);
+        }
+    }
+
+    /// Draws a line segment as a single rotated quad, `thickness` pixels wide.
+    /// The ends are cut off straight (butt caps).
+    pub fn draw_line(&mut self, from: Vec2, to: Vec2, thickness: u16, color: Color, z: i16) {
+        let material = Material {
+            base: MaterialBase {},
+            kind: MaterialKind::Quad,
+        };
+
+        self.items.push(RenderItem {
+            position: Vec3::new(from.x, from.y, z),
+            material_ref: MaterialRef::from(material),
+            clip: self.current_clip(),
+            layer: self.current_layer,
+            target: self.current_target,
+            renderable: Renderable::Line(Line {
+                delta: to - from,
+                thickness,
+                color,
+            }),
+        });
+    }
+
+    #[allow(clippy::too_many_arguments)]
+    pub fn draw_nine_slice(
+        &mut self,
+        position: Vec3,
+        size: UVec2,
+        slices: Slices,
+        material_ref: &MaterialRef,
+        color: Color,
+    ) {
+        self.items.push(RenderItem {
+            position,
+            material_ref: material_ref.clone(),
+            clip: self.current_clip(),
+            layer: self.current_layer,
+            target: self.current_target,
+            renderable: Renderable::NineSlice(NineSlice {
+                size,
+                slices,
+                color,
+                origin_in_atlas: UVec2::new(0, 0),
+                size_inside_atlas: None,
+                mode: NineSliceMode::Tile,
+                draw_center: true,
+            }),
+        });
+    }
+
+    #[must_use]
+    pub const fn clear_color(&self) -> wgpu::Color {
+        self.clear_color
+    }
+
+    /// Sets the color that the virtual surface is cleared to before the items are rendered.
+    pub fn set_clear_color(&mut self, color: Color) {
+        self.clear_color = to_wgpu_color(color);
+    }
+
+    /// Sets the color of the bars around the virtual surface, when the aspect ratio
+    /// of the window does not match.
+    pub fn set_letterbox_color(&mut self, color: Color) {
+        self.screen_clear_color = to_wgpu_color(color);
+    }
+
+    #[must_use]
+    pub const fn letterbox_color(&self) -> wgpu::Color {
+        self.screen_clear_color
+    }
+
+    /// Stretches a texture over the whole window before the virtual surface is drawn,
+    /// so the bars around it show an image (e.g. a bezel) instead of the letterbox color.
+    /// The screen effect is applied to it as well.
+    pub fn set_letterbox_texture(&mut self, texture: Option<&TextureRef>) {
+        self.letterbox_texture = texture.cloned();
+    }
+
+    #[must_use]
+    pub const fn letterbox_texture(&self) -> Option<&TextureRef> {
+        self.letterbox_texture.as_ref()
+    }
+
+    #[must_use]
+    pub const fn surface_texture_format(&self) -> TextureFormat {
+        self.surface_texture_format
+    }
+
+    #[must_use]
+    pub const fn virtual_texture_format(&self) -> TextureFormat {
+        self.virtual_texture_format
+    }
+
+    /// For creating custom pipelines and resources, see [`Self::add_custom_pass`].
+    #[must_use]
+    pub fn device(&self) -> &Device {
+        &self.device
+    }
+
+    #[must_use]
+    pub fn queue(&self) -> &wgpu::Queue {
+        &self.queue
+    }
+
+    /// Adds a pass that is called every frame, after the game has been rendered to the
+    /// virtual surface and before that is drawn to the window. The passes are called in the
+    /// order they were added, with the view of the virtual surface.
+    pub fn add_custom_pass(
         &mut self,
-        position: Vec3,
-        size: UVec2,
-        slices: Slices,
-        material_ref: &MaterialRef,
-        color: Color,
+        pass: impl FnMut(&mut CommandEncoder, &TextureView) + Send + Sync + 'static,
     ) {
-        self.items.push(RenderItem {
-            position,
-            material_ref: material_ref.clone(),
-            renderable: Renderable::NineSlice(NineSlice {
-                size,
-                slices,
-                color,
-                origin_in_atlas: UVec2::new(0, 0),
-                size_inside_atlas: None,
-            }),
-        });
+        self.custom_passes.push(Box::new(pass));
+    }
+
+    /// Sets the effect that is applied when the virtual surface is drawn to the screen.
+    pub const fn set_screen_effect(&mut self, screen_effect: ScreenEffect) {
+        self.screen_effect = screen_effect;
     }
 
     #[must_use]
-    pub const fn clear_color(&self) -> wgpu::Color {
-        self.clear_color
+    pub const fn screen_effect(&self) -> ScreenEffect {
+        self.screen_effect
+    }
+
+    fn write_screen_effect_uniform(&self) {
+        let defaults = ScreenEffectUniform {
+            // Wrapped to keep the f32 precise enough for the animation
+            time: (self.last_render_at.absolute_milliseconds() % 3_600_000) as f32 / 1000.0,
+            virtual_size: [
+                f32::from(self.virtual_surface_size.x),
+                f32::from(self.virtual_surface_size.y),
+            ],
+            ..ScreenEffectUniform::default()
+        };
+        let uniform = match self.screen_effect {
+            ScreenEffect::None => defaults,
+            ScreenEffect::Desaturate {
+                saturation,
+                brightness,
+            } => ScreenEffectUniform {
+                saturation,
+                brightness,
+                ..defaults
+            },
+            ScreenEffect::Crt { intensity } => ScreenEffectUniform {
+                crt_intensity: intensity,
+                ..defaults
+            },
+        };
+        self.queue.write_buffer(
+            &self.screen_effect_uniform_buffer,
+            0,
+            bytemuck::cast_slice(&[uniform]),
+        );
     }
 
     // first two is multiplier and second pair is offset
-    fn calculate_texture_coords_mul_add(atlas_rect: URect, texture_size: UVec2) -> Vec4 {
-        let x = f32::from(atlas_rect.position.x) / f32::from(texture_size.x);
-        let y = f32::from(atlas_rect.position.y) / f32::from(texture_size.y);
-        let width = f32::from(atlas_rect.size.x) / f32::from(texture_size.x);
-        let height = f32::from(atlas_rect.size.y) / f32::from(texture_size.y);
-        Vec4([width, height, x, y])
-    }
-
-    fn order_render_items_in_batches(&mut self) -> Vec<Vec<&RenderItem>> {
-        let mut material_batches: Vec<Vec<&RenderItem>> = Vec::new();
-        let mut current_batch: Vec<&RenderItem> = Vec::new();
-        let mut current_material: Option<MaterialRef> = None;
-
-        for render_item in &self.items {
-            if Some(&render_item.material_ref) != current_material.as_ref() {
-                if !current_batch.is_empty() {
-                    material_batches.push(current_batch.clone());
-                    current_batch.clear();
+    fn calculate_texture_coords_mul_add(
+        atlas_rect: URect,
+        texture_size: UVec2,
+        half_texel_inset: bool,
+    ) -> Vec4 {
+        let texel_width = 1.0 / f32::from(texture_size.x);
+        let texel_height = 1.0 / f32::from(texture_size.y);
+        let x = f32::from(atlas_rect.position.x) * texel_width;
+        let y = f32::from(atlas_rect.position.y) * texel_height;
+        let width = f32::from(atlas_rect.size.x) * texel_width;
+        let height = f32::from(atlas_rect.size.y) * texel_height;
+        if half_texel_inset {
+            // Shrink the rect half a texel on each side, so filtering never reaches the neighbors
+            Vec4([
+                (width - texel_width).max(0.0),
+                (height - texel_height).max(0.0),
+                x + texel_width * 0.5,
+                y + texel_height * 0.5,
+            ])
+        } else {
+            Vec4([width, height, x, y])
+        }
+    }
+
+    /// Returns the ranges of the (sorted) items that can be drawn together.
+    fn order_render_items_in_batches(&self) -> Vec<Range<usize>> {
+        let mut material_batches: Vec<Range<usize>> = Vec::new();
+        let mut batch_start = 0;
+        let mut current_material: Option<&Material> = None;
+
+        let mut current_clip: Option<URect> = None;
+        let mut current_layer: Option<LayerId> = None;
+        let mut current_target: Option<Option<WeakTextureRef>> = None;
+
+        for (index, render_item) in self.items.iter().enumerate() {
+            // Only adjacent items are merged, so the z-order from the sort is kept
+            if !current_material
+                .is_some_and(|material| material.is_batch_compatible(&render_item.material_ref))
+                || !clip_rects_equal(render_item.clip, current_clip)
+                || current_layer != Some(render_item.layer)
+                || current_target != Some(render_item.target)
+            {
+                current_clip = render_item.clip;
+                current_layer = Some(render_item.layer);
+                current_target = Some(render_item.target);
+                if index > batch_start {
+                    material_batches.push(batch_start..index);
                 }
-                current_material = Some(render_item.material_ref.clone());
+                batch_start = index;
+                current_material = Some(&render_item.material_ref);
             }
-            current_batch.push(render_item);
         }
 
-        if !current_batch.is_empty() {
-            material_batches.push(current_batch);
+        if self.items.len() > batch_start {
+            material_batches.push(batch_start..self.items.len());
         }
 
         material_batches
@@ -676,7 +2165,7 @@ impl Render {
                 * Matrix4::from_scale(f32::from(quad_size.x), f32::from(quad_size.y), 1.0);
 
         let tex_coords_mul_add =
-            Self::calculate_texture_coords_mul_add(render_atlas, current_texture_size);
+            Self::calculate_texture_coords_mul_add(render_atlas, current_texture_size, false);
 
         let rotation_value = 0;
 
@@ -699,30 +2188,54 @@ impl Render {
         const FLIP_X_MASK: u32 = 0b0000_0100;
         const FLIP_Y_MASK: u32 = 0b0000_1000;
 
+        let camera_rect = self.camera_world_rect();
+        // Render targets are drawn without the camera, so they are culled against their own size
+        let target_rects: Vec<(WeakTextureRef, Rect)> = self
+            .render_targets
+            .iter()
+            .map(|render_target| {
+                (
+                    render_target.texture_ref,
+                    Rect::new(0, 0, render_target.size.x, render_target.size.y),
+                )
+            })
+            .collect();
+        let depth_buffer_enabled = self.depth_buffer_enabled;
+        let half_texel_inset = self.half_texel_inset;
+        let quad_limits = self.quad_limits;
+        let quad_flags = if self.quad_antialiasing {
+            QUAD_ANTIALIAS_FLAG
+        } else {
+            0
+        };
         let batches = self.sort_and_put_in_batches();
 
         let mut quad_matrix_and_uv: Vec<SpriteInstanceUniform> = Vec::new();
-        let mut batch_vertex_ranges: Vec<(MaterialRef, u32, u32)> = Vec::new();
+        let mut batch_vertex_ranges: Vec<Batch> = Vec::new();
+        let mut stats = RenderStats::default();
+        let mut dropped_quads = 0;
 
-        for render_items in batches {
+        for batch_range in batches {
+            let render_items = &self.items[batch_range];
             let quad_len_before = quad_matrix_and_uv.len();
 
-            // Fix: Access material_ref through reference and copy it
-            let weak_material_ref = render_items
+            // Borrowed, the `MaterialRef` is only cloned once for the `Batch`
+            let material = &render_items
                 .first()
-                .map(|item| {
-                    // Force copy semantics by dereferencing the shared reference
-                    let material_ref: MaterialRef = item.material_ref.clone();
-                    material_ref
-                })
-                .expect("Render items batch was empty");
+                .expect("Render items batch was empty")
+                .material_ref;
 
-            if !weak_material_ref.is_complete(textures) {
+            if !material.is_complete(textures) {
                 // Material is not loaded yet
-                trace!(?weak_material_ref, "material is not complete yet");
+                trace!(?material, "material is not complete yet");
                 continue;
             }
-            let material = weak_material_ref.clone();
+            let clip = render_items.first().and_then(|item| item.clip);
+            let layer = render_items
+                .first()
+                .map(|item| item.layer)
+                .unwrap_or_default();
+            let target = render_items.first().and_then(|item| item.target);
 
             let maybe_texture_ref = material.primary_texture();
             let maybe_texture = maybe_texture_ref
@@ -733,6 +2246,7 @@ impl Render {
 
                 match &render_item.renderable {
                     Renderable::Sprite(sprite) => {
+                        stats.sprites += 1;
                         let current_texture_size = maybe_texture.unwrap().texture_size;
 
                         let params = &sprite.params;
@@ -746,69 +2260,71 @@ impl Render {
                             size,
                         };
 
-                        match params.rotation {
-                            Rotation::Degrees90 | Rotation::Degrees270 => {
-                                swap(&mut size.x, &mut size.y);
-                            }
-                            _ => {}
-                        }
-
-                        let y_offset = if params.anchor == Anchor::UpperLeft {
-                            current_texture_size.y as i16
-                        } else {
-                            0
-                        };
-
-                        let model_matrix = Matrix4::from_translation(
-                            f32::from(render_item.position.x),
-                            f32::from(render_item.position.y - y_offset),
-                            0.0,
-                        ) * Matrix4::from_scale(
-                            f32::from(size.x * u16::from(params.scale)),
-                            f32::from(size.y * u16::from(params.scale)),
-                            1.0,
+                        let model_matrix = params.model_matrix(
+                            Vec2::new(render_item.position.x, render_item.position.y),
+                            size,
                         );
 
                         let tex_coords_mul_add = Self::calculate_texture_coords_mul_add(
                             render_atlas,
                             current_texture_size,
+                            half_texel_inset,
                         );
 
-                        let mut rotation_value = match params.rotation {
+                        let rotation_value = match params.rotation {
                             Rotation::Degrees0 => 0,
                             Rotation::Degrees90 => 1,
                             Rotation::Degrees180 => 2,
                             Rotation::Degrees270 => 3,
                         };
 
-                        if params.flip_x {
-                            rotation_value |= FLIP_X_MASK;
-                        }
-                        if params.flip_y {
-                            rotation_value |= FLIP_Y_MASK;
-                        }
-
-                        let quad_instance = SpriteInstanceUniform::new(
-                            model_matrix,
-                            tex_coords_mul_add,
-                            rotation_value,
-                            Vec4(params.color.to_f32_slice()),
-                        );
+                        let [flash_r, flash_g, flash_b, _] = params.flash_color.to_f32_slice();
+                        let secondary_color =
+                            if matches!(material.kind, MaterialKind::Outline { .. }) {
+                                Vec4(params.outline_color.to_f32_slice())
+                            } else {
+                                Vec4([flash_r, flash_g, flash_b, params.flash.clamp(0.0, 1.0)])
+                            };
+                        let quad_instance = SpriteInstanceUniform {
+                            add_color: Vec4(params.add_color.to_f32_slice()),
+                            ..SpriteInstanceUniform::new_ex(
+                                model_matrix,
+                                tex_coords_mul_add,
+                                rotation_value,
+                                Vec4(params.color.to_f32_slice()),
+                                secondary_color,
+                            )
+                        };
                         quad_matrix_and_uv.push(quad_instance);
                     }
 
-                    Renderable::Mask(texture_offset, color) => {
+                    Renderable::Mask(texture_offset, mask_offset, color) => {
                         let current_texture_size = maybe_texture.unwrap().texture_size;
+                        let mask_tex_coords_offset = match &material.kind {
+                            MaterialKind::AlphaMasker { alpha_texture, .. } => {
+                                textures.get(alpha_texture).map_or(Vec4([0.0; 4]), |alpha| {
+                                    Vec4([
+                                        f32::from(mask_offset.x) / f32::from(alpha.texture_size.x),
+                                        f32::from(mask_offset.y) / f32::from(alpha.texture_size.y),
+                                        0.0,
+                                        0.0,
+                                    ])
+                                })
+                            }
+                            _ => Vec4([0.0; 4]),
+                        };
                         let params = SpriteParams {
                             texture_size: current_texture_size,
                             texture_pos: *texture_offset,
                             scale: 1,
+                            scale_f32: None,
                             rotation: Rotation::default(),
                             flip_x: false,
                             flip_y: false,
                             pivot: Vec2 { x: 0, y: 0 },
                             color: *color,
                             anchor: Anchor::LowerLeft,
+                            ..Default::default()
                         };
 
                         let mut size = params.texture_size;
@@ -821,19 +2337,18 @@ impl Render {
                             size,
                         };
 
-                        let model_matrix = Matrix4::from_translation(
-                            f32::from(render_item.position.x),
-                            f32::from(render_item.position.y),
-                            0.0,
-                        ) * Matrix4::from_scale(
-                            f32::from(size.x * u16::from(params.scale)),
-                            f32::from(size.y * u16::from(params.scale)),
-                            1.0,
-                        );
+                        let (scaled_width, scaled_height) = params.scaled_size(size);
+                        let model_matrix =
+                            Matrix4::from_translation(
+                                f32::from(render_item.position.x),
+                                f32::from(render_item.position.y),
+                                0.0,
+                            ) * Matrix4::from_scale(scaled_width, scaled_height, 1.0);
 
                         let tex_coords_mul_add = Self::calculate_texture_coords_mul_add(
                             render_atlas,
                             current_texture_size,
+                            half_texel_inset,
                         );
 
                         let mut rotation_value = match params.rotation {
@@ -850,33 +2365,35 @@ impl Render {
                             rotation_value |= FLIP_Y_MASK;
                         }
 
-                        let quad_instance = SpriteInstanceUniform::new(
+                        // The mask shader reads the mask offset from the secondary color
+                        let quad_instance = SpriteInstanceUniform::new_ex(
                             model_matrix,
                             tex_coords_mul_add,
                             rotation_value,
                             Vec4(params.color.to_f32_slice()),
+                            mask_tex_coords_offset,
                         );
                         quad_matrix_and_uv.push(quad_instance);
                     }
 
                     Renderable::NineSlice(nine_slice) => {
                         let current_texture_size = maybe_texture.unwrap().texture_size;
-                        Self::prepare_nine_slice(
-                            nine_slice,
-                            render_item.position,
-                            &mut quad_matrix_and_uv,
-                            current_texture_size,
-                        );
-                    }
-
-                    Renderable::NineSliceStretch(nine_slice) => {
-                        let current_texture_size = maybe_texture.unwrap().texture_size;
-                        Self::prepare_nine_slice_single_center_quad(
-                            nine_slice,
-                            render_item.position,
-                            &mut quad_matrix_and_uv,
-                            current_texture_size,
-                        );
+                        match nine_slice.mode {
+                            NineSliceMode::Tile => Self::prepare_nine_slice(
+                                nine_slice,
+                                render_item.position,
+                                &mut quad_matrix_and_uv,
+                                current_texture_size,
+                            ),
+                            NineSliceMode::Stretch => {
+                                Self::prepare_nine_slice_single_center_quad(
+                                    nine_slice,
+                                    render_item.position,
+                                    &mut quad_matrix_and_uv,
+                                    current_texture_size,
+                                );
+                            }
+                        }
                     }
 
                     Renderable::QuadColor(quad) => {
@@ -895,13 +2412,122 @@ impl Render {
                             0.0, //y
                             0.0, 0.0,
                         ]);
-                        let rotation_value = 0;
+                        let rotation_value = quad_flags;
 
-                        let quad_instance = SpriteInstanceUniform::new(
+                        let quad_instance = SpriteInstanceUniform::new_ex(
                             model_matrix,
                             tex_coords_mul_add,
                             rotation_value,
                             Vec4(quad.color.to_f32_slice()),
+                            Vec4(quad.color.to_f32_slice()),
+                        );
+                        quad_matrix_and_uv.push(quad_instance);
+                    }
+
+                    Renderable::QuadGradient(gradient) => {
+                        let model_matrix = Matrix4::from_translation(
+                            f32::from(render_item.position.x),
+                            f32::from(render_item.position.y),
+                            0.0,
+                        ) * Matrix4::from_scale(
+                            f32::from(gradient.size.x),
+                            f32::from(gradient.size.y),
+                            1.0,
+                        );
+
+                        let gradient_direction = match gradient.direction {
+                            GradientDirection::Vertical => 0,
+                            GradientDirection::Horizontal => 1,
+                        } | quad_flags;
+
+                        let quad_instance = SpriteInstanceUniform::new_ex(
+                            model_matrix,
+                            Vec4([0.0, 0.0, 0.0, 0.0]),
+                            gradient_direction,
+                            Vec4(gradient.start_color.to_f32_slice()),
+                            Vec4(gradient.end_color.to_f32_slice()),
+                        );
+                        quad_matrix_and_uv.push(quad_instance);
+                    }
+
+                    Renderable::Circle(circle) => {
+                        let model_matrix = Matrix4::from_translation(
+                            f32::from(render_item.position.x) - f32::from(circle.radii.x),
+                            f32::from(render_item.position.y) - f32::from(circle.radii.y),
+                            0.0,
+                        ) * Matrix4::from_scale(
+                            f32::from(circle.radii.x) * 2.0,
+                            f32::from(circle.radii.y) * 2.0,
+                            1.0,
+                        );
+
+                        let quad_instance = SpriteInstanceUniform::new(
+                            model_matrix,
+                            Vec4([0.0, 0.0, 0.0, 0.0]),
+                            0,
+                            Vec4(circle.color.to_f32_slice()),
+                        );
+                        quad_matrix_and_uv.push(quad_instance);
+                    }
+
+                    Renderable::RoundedQuad(rounded_quad) => {
+                        let model_matrix = Matrix4::from_translation(
+                            f32::from(render_item.position.x),
+                            f32::from(render_item.position.y),
+                            0.0,
+                        ) * Matrix4::from_scale(
+                            f32::from(rounded_quad.size.x),
+                            f32::from(rounded_quad.size.y),
+                            1.0,
+                        );
+
+                        let quad_instance = SpriteInstanceUniform::new_ex(
+                            model_matrix,
+                            Vec4([0.0, 0.0, 0.0, 0.0]),
+                            0,
+                            Vec4(rounded_quad.color.to_f32_slice()),
+                            Vec4([
+                                f32::from(rounded_quad.size.x),
+                                f32::from(rounded_quad.size.y),
+                                f32::from(rounded_quad.radius),
+                                0.0,
+                            ]),
+                        );
+                        quad_matrix_and_uv.push(quad_instance);
+                    }
+
+                    Renderable::Line(line) => {
+                        let delta_x = f32::from(line.delta.x);
+                        let delta_y = f32::from(line.delta.y);
+                        let length = delta_x.hypot(delta_y);
+                        if length == 0.0 {
+                            continue;
+                        }
+                        let (sin, cos) = (delta_y / length, delta_x / length);
+                        let thickness = f32::from(line.thickness);
+
+                        let rotation_matrix = Matrix4::from([
+                            [cos, sin, 0.0, 0.0],
+                            [-sin, cos, 0.0, 0.0],
+                            [0.0, 0.0, 1.0, 0.0],
+                            [0.0, 0.0, 0.0, 1.0],
+                        ]);
+
+                        // The unit quad is rotated around `from`, centered on the line
+                        let model_matrix = Matrix4::from_translation(
+                            f32::from(render_item.position.x),
+                            f32::from(render_item.position.y),
+                            0.0,
+                        ) * rotation_matrix
+                            * Matrix4::from_translation(0.0, -thickness / 2.0, 0.0)
+                            * Matrix4::from_scale(length, thickness, 1.0);
+
+                        let quad_instance = SpriteInstanceUniform::new_ex(
+                            model_matrix,
+                            Vec4([0.0, 0.0, 0.0, 0.0]),
+                            quad_flags,
+                            Vec4(line.color.to_f32_slice()),
+                            Vec4(line.color.to_f32_slice()),
                         );
                         quad_matrix_and_uv.push(quad_instance);
                     }
@@ -914,45 +2540,108 @@ impl Render {
                         }
                         let font = result.unwrap();
 
-                        let glyph_draw = font.draw(&text.text);
-                        for glyph in glyph_draw.glyphs {
-                            let pos = render_item.position + Vec3::from(glyph.relative_position);
-                            let texture_size = glyph.texture_rectangle.size;
-                            let model_matrix =
-                                Matrix4::from_translation(f32::from(pos.x), f32::from(pos.y), 0.0)
-                                    * Matrix4::from_scale(
-                                        f32::from(texture_size.x),
-                                        f32::from(texture_size.y),
-                                        1.0,
-                                    );
-                            let tex_coords_mul_add = Self::calculate_texture_coords_mul_add(
-                                glyph.texture_rectangle,
-                                current_texture_size,
-                            );
+                        // Copies that are behind the text are emitted first, so they are
+                        // drawn first in the same batch. `None` color is the text itself.
+                        let mut copies: Vec<(Vec2, Option<Color>)> = Vec::with_capacity(10);
+                        if let Some((offset, shadow_color)) = text.shadow {
+                            copies.push((offset, Some(shadow_color)));
+                        }
+                        if let Some(outline_color) = text.outline {
+                            for y in -1..=1 {
+                                for x in -1..=1 {
+                                    if x != 0 || y != 0 {
+                                        copies.push((Vec2::new(x, y), Some(outline_color)));
+                                    }
+                                }
+                            }
+                        }
+                        copies.push((Vec2::new(0, 0), None));
 
-                            let quad_instance = SpriteInstanceUniform::new(
-                                model_matrix,
-                                tex_coords_mul_add,
-                                0,
-                                Vec4(text.color.to_f32_slice()),
-                            );
-                            quad_matrix_and_uv.push(quad_instance);
+                        let glyph_draw = font.draw(&text.text);
+                        stats.text_glyphs += glyph_draw.glyphs.len();
+                        for (copy_offset, copy_color) in copies {
+                            for glyph in &glyph_draw.glyphs {
+                                let line_width = glyph_draw.line_widths[glyph.line_index] as i16;
+                                let align_offset = match text.align {
+                                    TextAlign::Left => 0,
+                                    TextAlign::Center => -(line_width / 2),
+                                    TextAlign::Right => -line_width,
+                                };
+                                let pos = render_item.position
+                                    + Vec3::from(glyph.relative_position)
+                                    + Vec3::from(copy_offset)
+                                    + Vec3::new(align_offset, 0, 0);
+                                let texture_size = glyph.texture_rectangle.size;
+                                let model_matrix = Matrix4::from_translation(
+                                    f32::from(pos.x),
+                                    f32::from(pos.y),
+                                    0.0,
+                                ) * Matrix4::from_scale(
+                                    f32::from(texture_size.x),
+                                    f32::from(texture_size.y),
+                                    1.0,
+                                );
+                                let tex_coords_mul_add = Self::calculate_texture_coords_mul_add(
+                                    glyph.texture_rectangle,
+                                    current_texture_size,
+                                    half_texel_inset,
+                                );
+
+                                let glyph_color = copy_color
+                                    .unwrap_or_else(|| text.color_for_char(glyph.char_index));
+                                let quad_instance = SpriteInstanceUniform::new(
+                                    model_matrix,
+                                    tex_coords_mul_add,
+                                    0,
+                                    Vec4(glyph_color.to_f32_slice()),
+                                );
+                                quad_matrix_and_uv.push(quad_instance);
+                            }
                         }
                     }
 
                     Renderable::TileMap(tile_map) => {
-                        for (index, tile) in tile_map.tiles.iter().enumerate() {
-                            let cell_pos_x = (index as u16 % tile_map.tiles_data_grid_size.x)
-                                * tile_map.one_cell_size.x
-                                * u16::from(tile_map.scale);
-                            let cell_pos_y = (index as u16 / tile_map.tiles_data_grid_size.x)
-                                * tile_map.one_cell_size.y
-                                * u16::from(tile_map.scale);
-                            let cell_x = *tile % tile_map.cell_count_size.x;
-                            let cell_y = *tile / tile_map.cell_count_size.x;
-
-                            let tex_x = cell_x * tile_map.one_cell_size.x;
-                            let tex_y = cell_y * tile_map.one_cell_size.x;
+                        let cull_rect = render_item.target.map_or(camera_rect, |target| {
+                            target_rects
+                                .iter()
+                                .find(|(texture_ref, _)| *texture_ref == target)
+                                .map_or(camera_rect, |(_, rect)| *rect)
+                        });
+                        let visible = tile_map.visible_range(
+                            Vec2::new(render_item.position.x, render_item.position.y),
+                            cull_rect,
+                        );
+                        let grid_width = usize::from(tile_map.tiles_data_grid_size.x);
+                        let visible_indices = (visible.position.y
+                            ..visible.position.y + visible.size.y)
+                            .flat_map(|row| {
+                                let start =
+                                    usize::from(row) * grid_width + usize::from(visible.position.x);
+                                start..start + usize::from(visible.size.x)
+                            });
+                        for index in visible_indices {
+                            // The last row can be partial
+                            let Some(tile) = tile_map.tiles.get(index) else {
+                                continue;
+                            };
+                            if *tile & TILE_INDEX_MASK == TILE_EMPTY {
+                                continue;
+                            }
+                            // In i32, since large maps are wider than u16 pixels
+                            let cell_pos_x = (index % grid_width) as i32
+                                * i32::from(tile_map.one_cell_size.x)
+                                * i32::from(tile_map.scale);
+                            let cell_pos_y = (index / grid_width) as i32
+                                * i32::from(tile_map.one_cell_size.y)
+                                * i32::from(tile_map.scale);
+                            let tile_index = *tile & TILE_INDEX_MASK;
+                            let cell_x = tile_index % tile_map.cell_count_size.x;
+                            let cell_y = tile_index / tile_map.cell_count_size.x;
+
+                            let tex_x = tile_map.margin.x
+                                + cell_x * (tile_map.one_cell_size.x + tile_map.spacing.x);
+                            let tex_y = tile_map.margin.y
+                                + cell_y * (tile_map.one_cell_size.y + tile_map.spacing.y);
 
                             let cell_texture_area = URect::new(
                                 tex_x,
@@ -962,12 +2651,12 @@ impl Render {
                             );
 
                             let cell_model_matrix = Matrix4::from_translation(
-                                f32::from(render_item.position.x + cell_pos_x as i16),
-                                f32::from(render_item.position.y + cell_pos_y as i16),
+                                (i32::from(render_item.position.x) + cell_pos_x) as f32,
+                                (i32::from(render_item.position.y) + cell_pos_y) as f32,
                                 0.0,
                             ) * Matrix4::from_scale(
-                                f32::from(tile_map.one_cell_size.x * u16::from(tile_map.scale)),
-                                f32::from(tile_map.one_cell_size.y * u16::from(tile_map.scale)),
+                                f32::from(tile_map.one_cell_size.x) * f32::from(tile_map.scale),
+                                f32::from(tile_map.one_cell_size.y) * f32::from(tile_map.scale),
                                 1.0,
                             );
 
@@ -975,12 +2664,32 @@ impl Render {
                             let cell_tex_coords_mul_add = Self::calculate_texture_coords_mul_add(
                                 cell_texture_area,
                                 current_texture_size,
+                                half_texel_inset,
                             );
 
+                            // Same order as Tiled: diagonal flip first, then horizontal and vertical
+                            let flip_x = *tile & TILE_FLIP_X_FLAG != 0;
+                            let flip_y = *tile & TILE_FLIP_Y_FLAG != 0;
+                            let rotation_value = if *tile & TILE_FLIP_DIAGONAL_FLAG == 0 {
+                                match (flip_x, flip_y) {
+                                    (false, false) => 0,
+                                    (true, false) => FLIP_X_MASK,
+                                    (false, true) => FLIP_Y_MASK,
+                                    (true, true) => 2,
+                                }
+                            } else {
+                                match (flip_x, flip_y) {
+                                    (false, false) => 1 | FLIP_X_MASK,
+                                    (true, false) => 3,
+                                    (false, true) => 1,
+                                    (true, true) => 1 | FLIP_Y_MASK,
+                                }
+                            };
+
                             let quad_instance = SpriteInstanceUniform::new(
                                 cell_model_matrix,
                                 cell_tex_coords_mul_add,
-                                0,
+                                rotation_value,
                                 Vec4([1.0, 1.0, 1.0, 1.0]),
                             );
                             quad_matrix_and_uv.push(quad_instance);
@@ -988,40 +2697,71 @@ impl Render {
                     }
                 }
 
-                let quad_count_for_this_render_item =
-                    quad_matrix_and_uv.len() - quad_len_before_inner;
-                assert!(
-                    quad_count_for_this_render_item <= MAXIMUM_QUADS_FOR_RENDER_ITEM,
-                    "too many quads {quad_count_for_this_render_item} for render item {render_item:?}"
+                if depth_buffer_enabled {
+                    // The view projection maps a world z of -depth to a clip space z of depth
+                    let depth_matrix =
+                        Matrix4::from_translation(0.0, 0.0, -depth_from_z(render_item.position.z));
+                    for instance in &mut quad_matrix_and_uv[quad_len_before_inner..] {
+                        instance.model = depth_matrix * instance.model;
+                    }
+                }
+
+                dropped_quads += truncate_quads(
+                    &mut quad_matrix_and_uv,
+                    quad_len_before_inner,
+                    quad_limits.per_render_item,
                 );
             }
 
-            let quad_count_for_this_batch = quad_matrix_and_uv.len() - quad_len_before;
-            assert!(
-                quad_count_for_this_batch <= MAXIMUM_QUADS_IN_A_BATCH,
-                "too many quads {quad_count_for_this_batch} total to render in this batch"
+            dropped_quads += truncate_quads(
+                &mut quad_matrix_and_uv,
+                quad_len_before,
+                quad_limits.per_batch,
             );
+            dropped_quads += truncate_quads(&mut quad_matrix_and_uv, 0, quad_limits.per_render);
 
-            assert!(
-                quad_matrix_and_uv.len() <= MAXIMUM_QUADS_IN_ONE_RENDER,
-                "too many quads for whole render {}",
-                quad_matrix_and_uv.len()
-            );
+            let quad_count_for_this_batch = quad_matrix_and_uv.len() - quad_len_before;
 
-            batch_vertex_ranges.push((
-                weak_material_ref,
-                quad_len_before as u32,
-                quad_count_for_this_batch as u32,
-            ));
+            batch_vertex_ranges.push(Batch {
+                material: material.clone(),
+                start: quad_len_before as u32,
+                count: quad_count_for_this_batch as u32,
+                clip,
+                layer,
+                target,
+            });
         }
 
-        // write all model_matrix and uv_coords to instance buffer once, before the render pass
+        if self.pixel_snap {
+            for quad_instance in &mut quad_matrix_and_uv {
+                quad_instance.model = snap_translation(quad_instance.model);
+            }
+        }
+
+        // write all model_matrix and uv_coords to the next instance buffer once, before the render pass
+        self.instance_buffer_index =
+            (self.instance_buffer_index + 1) % self.quad_matrix_and_uv_instance_buffers.len();
         self.queue.write_buffer(
-            &self.quad_matrix_and_uv_instance_buffer,
+            &self.quad_matrix_and_uv_instance_buffers[self.instance_buffer_index],
             0,
             bytemuck::cast_slice(&quad_matrix_and_uv),
         );
 
+        stats.quads_dropped = dropped_quads > 0;
+        if stats.quads_dropped && !self.quad_limits_exceeded_logged {
+            // Only logged once, since it is likely to happen every frame
+            error!(
+                dropped_quads,
+                ?quad_limits,
+                "too many quads, the quads over the limits are not rendered"
+            );
+            self.quad_limits_exceeded_logged = true;
+        }
+
+        stats.batches = batch_vertex_ranges.len();
+        stats.quads = quad_matrix_and_uv.len();
+        self.last_frame_stats = stats;
+
         self.batch_offsets = batch_vertex_ranges;
     }
 
@@ -1180,8 +2920,12 @@ impl Render {
         // Calculate how many repetitions (quads) we need in each direction
         let repeat_x_count =
             (f32::from(world_edge_width) / f32::from(texture_edge_width)).ceil() as usize;
-        let repeat_y_count =
-            (f32::from(world_edge_height) / f32::from(texture_edge_height)).ceil() as usize;
+        // No rows at all when the center should be left empty
+        let repeat_y_count = if nine_slice.draw_center {
+            (f32::from(world_edge_height) / f32::from(texture_edge_height)).ceil() as usize
+        } else {
+            0
+        };
 
         for y in 0..repeat_y_count {
             for x in 0..repeat_x_count {
@@ -1492,7 +3236,9 @@ impl Render {
             color,
             current_texture_size,
         );
-        quad_matrix_and_uv.push(center_quad);
+        if nine_slice.draw_center {
+            quad_matrix_and_uv.push(center_quad);
+        }
 
         // Right edge
         let right_edge_pos = Vec3::new(
@@ -1586,8 +3332,8 @@ impl Render {
         quad_matrix_and_uv.push(top_right_quad);
     }
 
-    fn sort_and_put_in_batches(&mut self) -> Vec<Vec<&RenderItem>> {
-        sort_render_items_by_z_and_material(&mut self.items);
+    fn sort_and_put_in_batches(&mut self) -> Vec<Range<usize>> {
+        sort_render_items_by_z_and_material(&mut self.items, self.depth_buffer_enabled);
 
         self.order_render_items_in_batches()
     }
@@ -1606,6 +3352,7 @@ impl Render {
     ) {
         self.debug_tick += 1;
         trace!("start render()");
+        self.warn_if_slow_frame(now);
         self.last_render_at = now;
 
         self.set_viewport_and_view_projection_matrix();
@@ -1614,7 +3361,15 @@ impl Render {
 
         self.render_batches_to_virtual_texture(command_encoder, textures);
 
-        self.render_virtual_texture_to_display(command_encoder, display_surface_texture_view);
+        for custom_pass in &mut self.custom_passes {
+            custom_pass(command_encoder, &self.virtual_surface_texture_view);
+        }
+
+        self.render_virtual_texture_to_display(
+            command_encoder,
+            display_surface_texture_view,
+            textures,
+        );
     }
 
     pub fn set_viewport_and_view_projection_matrix(&mut self) {
@@ -1623,11 +3378,35 @@ impl Render {
             self.virtual_surface_size.y,
         );
 
-        let scale_matrix = Matrix4::from_scale(self.scale, self.scale, 0.0);
-        let origin_translation_matrix =
-            Matrix4::from_translation(f32::from(-self.origin.x), f32::from(-self.origin.y), 0.0);
+        // Without a depth buffer, z is flattened so everything is rendered at the same depth
+        let z_scale = if self.depth_buffer_enabled { 1.0 } else { 0.0 };
+        let scale_matrix = Matrix4::from_scale(self.scale, self.scale, z_scale);
+        let origin_translation_matrix = Matrix4::from_translation(
+            -(f32::from(self.origin.x) + self.origin_fraction.0),
+            -(f32::from(self.origin.y) + self.origin_fraction.1),
+            0.0,
+        );
+
+        // Zoom and rotation are done around the center of the virtual surface
+        let half_x = f32::from(self.virtual_surface_size.x) / 2.0;
+        let half_y = f32::from(self.virtual_surface_size.y) / 2.0;
+        let (sin, cos) = (-self.camera_rotation).sin_cos();
+        let camera_matrix = Matrix4::from_translation(half_x, half_y, 0.0)
+            * Matrix4::from([
+                [cos, sin, 0.0, 0.0],
+                [-sin, cos, 0.0, 0.0],
+                [0.0, 0.0, 1.0, 0.0],
+                [0.0, 0.0, 0.0, 1.0],
+            ])
+            * Matrix4::from_scale(self.camera_zoom, self.camera_zoom, 1.0)
+            * Matrix4::from_translation(-half_x, -half_y, 0.0);
+
+        let mut camera_matrix = camera_matrix * origin_translation_matrix;
+        if self.pixel_snap {
+            camera_matrix = snap_translation(camera_matrix);
+        }
 
-        let total_matrix = scale_matrix * view_proj_matrix * origin_translation_matrix;
+        let total_matrix = scale_matrix * view_proj_matrix * camera_matrix;
 
         // write all model_matrix and uv_coords to instance buffer once, before the render pass
         self.queue.write_buffer(
@@ -1635,73 +3414,312 @@ impl Render {
             0,
             bytemuck::cast_slice(&[total_matrix]),
         );
+
+        // Render targets are not affected by the camera, only by the depth setting
+        for render_target in &self.render_targets {
+            let target_matrix = Matrix4::from_scale(1.0, 1.0, z_scale)
+                * create_view_projection_matrix_from_virtual(
+                    render_target.size.x,
+                    render_target.size.y,
+                );
+            self.queue.write_buffer(
+                &render_target.camera_buffer,
+                0,
+                bytemuck::cast_slice(&[target_matrix]),
+            );
+        }
+    }
+
+    /// Processes the currently pushed items and draws them straight into `target`,
+    /// bypassing the virtual surface and the virtual-to-screen blit.
+    ///
+    /// This is an advanced API, intended for tools (e.g. thumbnail renderers) that
+    /// want to compose frames manually. The target is *not* cleared, so several flushes
+    /// can be composed into the same view. The items are cleared afterwards, and the
+    /// normal [`Self::render`] path is unaffected. Since the size of `target` is not known,
+    /// clip rectangles are not applied. `target` must have the virtual texture format.
+    ///
+    /// # Panics
+    ///
+    pub fn flush(
+        &mut self,
+        command_encoder: &mut CommandEncoder,
+        target: &TextureView,
+        textures: &Assets<Texture>,
+        fonts: &Assets<Font>,
+        now: Millis,
+    ) {
+        trace!("start flush()");
+        self.last_render_at = now;
+
+        self.set_viewport_and_view_projection_matrix();
+
+        self.write_vertex_indices_and_uv_to_buffer(textures, fonts);
+
+        let target_size = target.texture().size();
+        if self
+            .flush_depth
+            .as_ref()
+            .is_none_or(|(size, _)| *size != target_size)
+        {
+            self.flush_depth = Some((
+                target_size,
+                create_depth_texture_view(&self.device, target_size, "Flush Depth Texture"),
+            ));
+        }
+        let (_, depth_view) = self.flush_depth.as_ref().unwrap();
+
+        self.render_batches_to_render_targets(command_encoder, textures);
+
+        self.render_batches(
+            command_encoder,
+            &PassTarget {
+                color: target,
+                depth: depth_view,
+                load: wgpu::LoadOp::Load,
+                viewport_size: None,
+                camera_bind_group: &self.camera_bind_group,
+                label: "Flush Render Pass",
+            },
+            self.virtual_surface_batches(),
+            textures,
+        );
+
+        self.items.clear();
+    }
+
+    pub fn render_batches_to_virtual_texture(
+        &mut self,
+        command_encoder: &mut CommandEncoder,
+        textures: &Assets<Texture>,
+    ) {
+        let target_draw_calls = self.render_batches_to_render_targets(command_encoder, textures);
+
+        let draw_calls = self.render_batches(
+            command_encoder,
+            &PassTarget {
+                color: &self.virtual_surface_texture_view,
+                depth: &self.virtual_surface_depth_view,
+                load: wgpu::LoadOp::Clear(self.clear_color),
+                viewport_size: Some(self.virtual_surface_size),
+                camera_bind_group: &self.camera_bind_group,
+                label: "Game Render Pass",
+            },
+            self.virtual_surface_batches(),
+            textures,
+        );
+        self.last_frame_stats.draw_calls = target_draw_calls + draw_calls;
+
+        self.items.clear();
+    }
+
+    /// Renders the batches for each render target that has items this frame.
+    /// The targets are cleared to transparent first. Returns the number of draw calls.
+    fn render_batches_to_render_targets(
+        &self,
+        command_encoder: &mut CommandEncoder,
+        textures: &Assets<Texture>,
+    ) -> usize {
+        let mut draw_calls = 0;
+        for target_batches in self
+            .batch_offsets
+            .chunk_by(|a, b| a.target == b.target)
+            .filter(|batches| batches[0].target.is_some())
+        {
+            let Some(render_target) = self
+                .render_targets
+                .iter()
+                .find(|render_target| Some(render_target.texture_ref) == target_batches[0].target)
+            else {
+                warn!("no render target found for pushed items");
+                continue;
+            };
+
+            draw_calls += self.render_batches(
+                command_encoder,
+                &PassTarget {
+                    color: &render_target.view,
+                    depth: &render_target.depth_view,
+                    load: wgpu::LoadOp::Clear(wgpu::Color::TRANSPARENT),
+                    viewport_size: Some(render_target.size),
+                    camera_bind_group: &render_target.camera_bind_group,
+                    label: "Render Target Pass",
+                },
+                target_batches,
+                textures,
+            );
+        }
+        draw_calls
+    }
+
+    /// The batches for the virtual surface, they are sorted after all render target batches.
+    fn virtual_surface_batches(&self) -> &[Batch] {
+        let first = self
+            .batch_offsets
+            .iter()
+            .position(|batch| batch.target.is_none())
+            .unwrap_or(self.batch_offsets.len());
+        &self.batch_offsets[first..]
+    }
+
+    /// Renders each layer in its own render pass, so every layer starts with a cleared depth buffer.
+    /// Only the first pass uses the `load` of `pass`, the following ones load what the previous
+    /// layers rendered. Returns the number of draw calls.
+    fn render_batches(
+        &self,
+        command_encoder: &mut CommandEncoder,
+        pass: &PassTarget,
+        batches: &[Batch],
+        textures: &Assets<Texture>,
+    ) -> usize {
+        if batches.is_empty() {
+            // Still needs a pass, for the clear
+            return self.render_layer_batches(command_encoder, pass, pass.load, &[], textures);
+        }
+
+        let mut layer_load = pass.load;
+        let mut draw_calls = 0;
+        for layer_batches in batches.chunk_by(|a, b| a.layer == b.layer) {
+            if let Some(color) = self.layer_clear(layer_batches[0].layer) {
+                layer_load = wgpu::LoadOp::Clear(color);
+            }
+            draw_calls += self.render_layer_batches(
+                command_encoder,
+                pass,
+                layer_load,
+                layer_batches,
+                textures,
+            );
+            layer_load = wgpu::LoadOp::Load;
+        }
+        draw_calls
     }
 
-    pub fn render_batches_to_virtual_texture(
-        &mut self,
+    fn render_layer_batches(
+        &self,
         command_encoder: &mut CommandEncoder,
+        pass: &PassTarget,
+        load: wgpu::LoadOp<wgpu::Color>,
+        batches: &[Batch],
         textures: &Assets<Texture>,
-    ) {
+    ) -> usize {
+        let viewport_size = pass.viewport_size;
         let mut render_pass = command_encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
-            label: Some("Game Render Pass"),
+            label: Some(pass.label),
             color_attachments: &[Some(wgpu::RenderPassColorAttachment {
-                view: &self.virtual_surface_texture_view,
+                view: pass.color,
                 depth_slice: None,
                 resolve_target: None,
                 ops: wgpu::Operations {
-                    load: wgpu::LoadOp::Clear(self.clear_color),
+                    load,
                     store: wgpu::StoreOp::Store,
                 },
             })],
-            depth_stencil_attachment: None,
+            depth_stencil_attachment: Some(wgpu::RenderPassDepthStencilAttachment {
+                view: pass.depth,
+                depth_ops: Some(wgpu::Operations {
+                    load: wgpu::LoadOp::Clear(1.0),
+                    store: wgpu::StoreOp::Discard,
+                }),
+                stencil_ops: None,
+            }),
             timestamp_writes: None,
             occlusion_query_set: None,
             multiview_mask: None,
         });
 
-        render_pass.set_viewport(
-            0.0,
-            0.0,
-            f32::from(self.virtual_surface_size.x),
-            f32::from(self.virtual_surface_size.y),
-            0.0,
-            1.0,
-        );
+        if let Some(viewport_size) = viewport_size {
+            render_pass.set_viewport(
+                0.0,
+                0.0,
+                f32::from(viewport_size.x),
+                f32::from(viewport_size.y),
+                0.0,
+                1.0,
+            );
+        }
 
         // Index and vertex buffers never change
         render_pass.set_index_buffer(self.index_buffer.slice(..), wgpu::IndexFormat::Uint16);
         render_pass.set_vertex_buffer(0, self.vertex_buffer.slice(..));
 
         // Vertex buffer is reused
-        render_pass.set_vertex_buffer(1, self.quad_matrix_and_uv_instance_buffer.slice(..));
+        render_pass.set_vertex_buffer(
+            1,
+            self.quad_matrix_and_uv_instance_buffers[self.instance_buffer_index].slice(..),
+        );
 
         let num_indices = mireforge_wgpu_sprites::INDICES.len() as u32;
 
         let mut current_pipeline: Option<&MaterialKind> = None;
-
-        for &(ref weak_material_ref, start, count) in &self.batch_offsets {
+        let debug_pipeline = self.debug_pipeline();
+
+        let mut current_clip: Option<URect> = None;
+        let mut draw_calls = 0;
+
+        for batch in batches {
+            let Batch {
+                material: ref weak_material_ref,
+                start,
+                count,
+                clip,
+                ..
+            } = *batch;
             let wgpu_material = weak_material_ref;
 
+            if let Some(target_size) = viewport_size
+                && !clip_rects_equal(clip, current_clip)
+            {
+                let scissor = clip.map_or(
+                    Some((0, 0, u32::from(target_size.x), u32::from(target_size.y))),
+                    |rect| Self::scissor_from_clip(rect, target_size),
+                );
+                // Clipped away completely, the scissor is left as it is
+                let Some((x, y, width, height)) = scissor else {
+                    continue;
+                };
+                current_clip = clip;
+                render_pass.set_scissor_rect(x, y, width, height);
+            }
+
             let pipeline_kind = &wgpu_material.kind;
 
             if current_pipeline != Some(pipeline_kind) {
                 let pipeline = match pipeline_kind {
-                    MaterialKind::NormalSprite { .. } => &self.normal_sprite_pipeline.pipeline,
+                    MaterialKind::NormalSprite { blend_mode, .. } => match blend_mode {
+                        BlendMode::Alpha => &self.normal_sprite_pipeline.pipeline,
+                        // The light shader is the normal sprite shader with additive blending
+                        BlendMode::Additive => &self.light_shader_info.pipeline,
+                        BlendMode::Multiply => &self.multiply_sprite_pipeline.pipeline,
+                        BlendMode::PremultipliedAlpha => {
+                            &self.premultiplied_sprite_pipeline.pipeline
+                        }
+                        BlendMode::Opaque => &self.opaque_sprite_pipeline.pipeline,
+                    },
                     MaterialKind::Quad => &self.quad_shader_info.pipeline,
+                    MaterialKind::Circle => &self.circle_shader_info.pipeline,
+                    MaterialKind::RoundedQuad => &self.rounded_quad_shader_info.pipeline,
                     MaterialKind::AlphaMasker { .. } => &self.mask_shader_info.pipeline,
                     MaterialKind::LightAdd { .. } => &self.light_shader_info.pipeline,
+                    MaterialKind::Outline { .. } => &self.outline_sprite_pipeline.pipeline,
                 };
+                let pipeline = debug_pipeline.unwrap_or(pipeline);
                 //trace!(%pipeline_kind, ?pipeline, "setting pipeline");
                 render_pass.set_pipeline(pipeline);
                 // Apparently after setting pipeline,
                 // you must set all bind groups again
                 current_pipeline = Some(pipeline_kind);
-                render_pass.set_bind_group(0, &self.camera_bind_group, &[]);
+                render_pass.set_bind_group(0, pass.camera_bind_group, &[]);
             }
 
             match &wgpu_material.kind {
-                MaterialKind::NormalSprite { primary_texture }
-                | MaterialKind::LightAdd { primary_texture } => {
+                // The debug pipelines only use the camera
+                _ if debug_pipeline.is_some() => {}
+                MaterialKind::NormalSprite {
+                    primary_texture, ..
+                }
+                | MaterialKind::LightAdd { primary_texture }
+                | MaterialKind::Outline { primary_texture } => {
                     let texture = textures.get(primary_texture).unwrap();
                     // Bind the texture and sampler bind group (Bind Group 1)
                     render_pass.set_bind_group(1, &texture.texture_and_sampler_bind_group, &[]);
@@ -1723,27 +3741,31 @@ impl Render {
                         &[],
                     );
                 }
-                MaterialKind::Quad => {
+                MaterialKind::Quad | MaterialKind::Circle | MaterialKind::RoundedQuad => {
                     // Intentionally do nothing
                 }
             }
             assert!(
-                count <= MAXIMUM_QUADS_IN_A_BATCH as u32,
+                count as usize <= self.quad_limits.per_batch,
                 "too many instanced draw in a batch {count}"
             );
 
             // Issue the instanced draw call for the batch
             trace!(material=%weak_material_ref, start=%start, count=%count, %num_indices, "draw instanced");
             render_pass.draw_indexed(0..num_indices, 0, start..(start + count));
+            draw_calls += 1;
         }
-        self.items.clear();
+        draw_calls
     }
 
     pub fn render_virtual_texture_to_display(
         &mut self,
         command_encoder: &mut CommandEncoder,
         display_surface_texture_view: &TextureView,
+        textures: &Assets<Texture>,
     ) {
+        self.write_screen_effect_uniform();
+
         let mut render_pass = command_encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
             label: Some("Screen Render Pass"),
             color_attachments: &[Some(wgpu::RenderPassColorAttachment {
@@ -1772,22 +3794,27 @@ impl Render {
         let viewport_y = (window_height as f32 - viewport_height) / 2.0;
          */
 
-        self.viewport = match self.viewport_strategy {
-            ViewportStrategy::FitIntegerScaling => Self::viewport_from_integer_scale(
-                self.physical_surface_size,
-                self.virtual_surface_size,
-            ),
-            ViewportStrategy::FitFloatScaling => Self::viewport_from_float_scale(
-                self.physical_surface_size,
-                self.virtual_surface_size,
-            ),
-            ViewportStrategy::MatchPhysicalSize => URect::new(
-                0,
-                0,
-                self.physical_surface_size.x,
-                self.physical_surface_size.y,
-            ),
-        };
+        self.update_viewport();
+
+        if let Some(letterbox_texture) = self
+            .letterbox_texture
+            .as_ref()
+            .and_then(|texture_ref| textures.get(texture_ref))
+        {
+            render_pass.set_viewport(
+                0.0,
+                0.0,
+                f32::from(self.physical_surface_size.x),
+                f32::from(self.physical_surface_size.y),
+                0.0,
+                1.0,
+            );
+            render_pass.set_pipeline(&self.virtual_to_screen_shader_info.pipeline);
+            render_pass.set_bind_group(0, &letterbox_texture.texture_and_sampler_bind_group, &[]);
+            render_pass.set_bind_group(1, &self.screen_effect_bind_group, &[]);
+            render_pass.set_vertex_buffer(0, self.vertex_buffer.slice(..));
+            render_pass.draw(0..6, 0..1);
+        }
 
         render_pass.set_viewport(
             f32::from(self.viewport.position.x),
@@ -1799,22 +3826,71 @@ impl Render {
         );
 
         // Draw the render texture to the screen
-        render_pass.set_pipeline(&self.virtual_to_screen_shader_info.pipeline);
+        let screen_pipeline = match self.screen_effect {
+            ScreenEffect::Crt { .. } => &self.crt_screen_shader_info.pipeline,
+            ScreenEffect::None | ScreenEffect::Desaturate { .. } => {
+                &self.virtual_to_screen_shader_info.pipeline
+            }
+        };
+        render_pass.set_pipeline(screen_pipeline);
         render_pass.set_bind_group(0, &self.virtual_to_surface_bind_group, &[]);
+        render_pass.set_bind_group(1, &self.screen_effect_bind_group, &[]);
         render_pass.set_vertex_buffer(0, self.vertex_buffer.slice(..));
 
         render_pass.draw(0..6, 0..1);
     }
 
+    /// Uploads tightly packed rgba octets of `size`, for textures that are generated at runtime.
+    ///
+    /// Like [`Self::create_render_target`], the returned [`Texture`] should be inserted
+    /// into the texture assets.
+    ///
+    /// # Panics
+    /// If `rgba` is not `size.x * size.y * 4` octets.
+    #[must_use]
+    pub fn texture_from_rgba(&self, rgba: &[u8], size: UVec2, label: &str) -> Texture {
+        let wgpu_texture = mireforge_wgpu_sprites::load_texture_from_rgba(
+            &self.device,
+            &self.queue,
+            rgba,
+            u32::from(size.x),
+            u32::from(size.y),
+            label,
+        );
+        self.texture_resource_from_texture(&wgpu_texture, label)
+    }
+
+    /// Textures with mipmaps are meant to be scaled down, so they get [`TextureFilter::Linear`],
+    /// the rest [`TextureFilter::Nearest`].
     pub fn texture_resource_from_texture(&self, texture: &wgpu::Texture, label: &str) -> Texture {
+        let filter = if texture.mip_level_count() > 1 {
+            TextureFilter::Linear
+        } else {
+            TextureFilter::Nearest
+        };
+        self.texture_resource_from_texture_ex(texture, label, filter)
+    }
+
+    /// The sampler is stored in the bind group of the texture, so materials using different
+    /// textures can use different filtering.
+    pub fn texture_resource_from_texture_ex(
+        &self,
+        texture: &wgpu::Texture,
+        label: &str,
+        filter: TextureFilter,
+    ) -> Texture {
         trace!("load texture from memory with name: '{label}'");
         let size = &texture.size();
+        let sampler = match filter {
+            TextureFilter::Nearest => &self.sampler,
+            TextureFilter::Linear => &self.trilinear_sampler,
+        };
         let texture_and_sampler_bind_group =
             mireforge_wgpu_sprites::create_sprite_texture_and_sampler_bind_group(
                 &self.device,
                 &self.texture_sampler_bind_group_layout,
                 texture,
-                &self.sampler,
+                sampler,
                 label,
             );
 
@@ -1827,6 +3903,11 @@ impl Render {
     }
 }
 
+/// Maps the whole `i16` range of z to a depth in `(0.0, 1.0]`, where higher z is closer.
+fn depth_from_z(z: i16) -> f32 {
+    0.5 - f32::from(z) / 65536.0
+}
+
 fn create_view_projection_matrix_from_virtual(virtual_width: u16, virtual_height: u16) -> Matrix4 {
     let (bottom, top) = (0.0, f32::from(virtual_height));
 
@@ -1867,8 +3948,76 @@ fn create_view_uniform_view_projection_matrix(viewport_size: UVec2) -> Matrix4 {
     view_projection_matrix.into()
 }
 
-fn sort_render_items_by_z_and_material(items: &mut [RenderItem]) {
-    items.sort_by_key(|item| (item.position.z, item.material_ref.clone()));
+fn clip_rects_equal(a: Option<URect>, b: Option<URect>) -> bool {
+    match (a, b) {
+        (Some(a), Some(b)) => a.position == b.position && a.size == b.size,
+        (None, None) => true,
+        _ => false,
+    }
+}
+
+/// The overlap of two rectangles, with a zero size if they do not overlap.
+fn intersect_rects(a: URect, b: URect) -> URect {
+    let left = u32::from(a.position.x.max(b.position.x));
+    let bottom = u32::from(a.position.y.max(b.position.y));
+    let right = (u32::from(a.position.x) + u32::from(a.size.x))
+        .min(u32::from(b.position.x) + u32::from(b.size.x));
+    let top = (u32::from(a.position.y) + u32::from(a.size.y))
+        .min(u32::from(b.position.y) + u32::from(b.size.y));
+
+    let to_u16 = |value: u32| u16::try_from(value).unwrap_or(u16::MAX);
+    URect::new(
+        to_u16(left.min(right)),
+        to_u16(bottom.min(top)),
+        to_u16(right.saturating_sub(left)),
+        to_u16(top.saturating_sub(bottom)),
+    )
+}
+
+/// Sorts on render target, layer, z, then material (so that items can be batched) and finally on the order the
+/// items were pushed. The last tie-breaker makes the order fully deterministic, items with
+/// the same z and material are always drawn in the order they were pushed.
+///
+/// With a depth buffer, the opaque items are placed first and ignore z, since the depth test
+/// takes care of their ordering.
+fn sort_render_items_by_z_and_material(items: &mut Vec<RenderItem>, depth_buffer_enabled: bool) {
+    // Render targets first, so the virtual surface can sample them
+    let sort_key = |item: &RenderItem| {
+        let target = (item.target.is_none(), item.target);
+        if depth_buffer_enabled && item.material_ref.is_opaque() {
+            (target, item.layer, false, 0)
+        } else {
+            (target, item.layer, true, item.position.z)
+        }
+    };
+    // Only the indices are moved around while sorting, the items are moved once at the end
+    let keys: Vec<_> = items.iter().map(sort_key).collect();
+    let mut order: Vec<usize> = (0..items.len()).collect();
+    order.sort_unstable_by(|&a_index, &b_index| {
+        keys[a_index]
+            .cmp(&keys[b_index])
+            .then_with(|| {
+                compare_materials(&items[a_index].material_ref, &items[b_index].material_ref)
+            })
+            .then(a_index.cmp(&b_index))
+    });
+
+    let mut unsorted: Vec<Option<RenderItem>> = items.drain(..).map(Some).collect();
+    items.extend(
+        order
+            .into_iter()
+            .map(|index| unsorted[index].take().expect("each index is used once")),
+    );
+}
+
+/// Orders materials by value, so separately created materials for the same texture end up next
+/// to each other and can be batched. The same `MaterialRef` is equal without comparing values.
+fn compare_materials(a: &MaterialRef, b: &MaterialRef) -> Ordering {
+    if Arc::ptr_eq(a, b) {
+        Ordering::Equal
+    } else {
+        a.cmp(b)
+    }
 }
 
 #[derive(Debug, Clone, Copy, Default)]
@@ -1884,19 +4033,53 @@ pub enum Rotation {
 pub enum Anchor {
     LowerLeft,
     UpperLeft,
+    /// The position is the center of the sprite, handy for rotating or scaling sprites in place.
+    Center,
+}
+
+/// Floating point scale, used when a sprite needs fractional scaling (e.g. 0.5x or 1.5x).
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct ScaleF32 {
+    pub x: f32,
+    pub y: f32,
+}
+
+impl ScaleF32 {
+    #[must_use]
+    pub const fn new(x: f32, y: f32) -> Self {
+        Self { x, y }
+    }
+
+    #[must_use]
+    pub const fn uniform(scale: f32) -> Self {
+        Self { x: scale, y: scale }
+    }
 }
 
 #[derive(Debug, Copy, Clone)]
 pub struct SpriteParams {
     pub texture_size: UVec2,
     pub texture_pos: UVec2,
+    /// Integer scale, keeps the sprite pixel perfect.
     pub scale: u8,
+    /// If set, overrides `scale` with a fractional scale. Not pixel perfect.
+    pub scale_f32: Option<ScaleF32>,
     pub rotation: Rotation,
     pub flip_x: bool,
     pub flip_y: bool,
+    /// Offset from the center of the sprite that `flip_x` and `flip_y` mirror around,
+    /// e.g. the feet of a character that turns around. In scaled pixels.
     pub pivot: Vec2,
     pub color: Color,
     pub anchor: Anchor,
+    /// How much the sprite is blended towards `flash_color`, from 0.0 (not at all) to 1.0
+    /// (solid `flash_color`). Useful for hit feedback.
+    pub flash: f32,
+    pub flash_color: Color,
+    /// Added to the tinted color, e.g. to make the sprite glow. The alpha is ignored.
+    pub add_color: Color,
+    /// Only used by [`Render::draw_sprite_outlined`].
+    pub outline_color: Color,
 }
 
 impl Default for SpriteParams {
@@ -1909,12 +4092,102 @@ impl Default for SpriteParams {
             flip_y: false,
             color: Color::from_octet(255, 255, 255, 255),
             scale: 1,
+            scale_f32: None,
             rotation: Rotation::Degrees0,
             anchor: Anchor::LowerLeft,
+            flash: 0.0,
+            flash_color: Color::from_octet(255, 255, 255, 255),
+            add_color: Color::from_octet(0, 0, 0, 0),
+            outline_color: Color::from_octet(255, 255, 255, 255),
+        }
+    }
+}
+
+impl SpriteParams {
+    /// Returns the world size of the quad, using `scale_f32` if set, otherwise `scale`.
+    #[must_use]
+    pub fn scaled_size(&self, size: UVec2) -> (f32, f32) {
+        self.scale_f32.map_or_else(
+            || {
+                (
+                    f32::from(size.x) * f32::from(self.scale),
+                    f32::from(size.y) * f32::from(self.scale),
+                )
+            },
+            |scale| (f32::from(size.x) * scale.x, f32::from(size.y) * scale.y),
+        )
+    }
+
+    /// The model matrix of the unit quad for a sprite at `position`, where `size` is the area
+    /// of the texture before it is rotated.
+    fn model_matrix(&self, position: Vec2, mut size: UVec2) -> Matrix4 {
+        match self.rotation {
+            Rotation::Degrees90 | Rotation::Degrees270 => {
+                swap(&mut size.x, &mut size.y);
+            }
+            _ => {}
         }
+
+        let (scaled_width, scaled_height) = self.scaled_size(size);
+
+        // Rotation is done on the texture coordinates, so the offsets only need
+        // the (already rotated) size. Rounded to stay pixel perfect.
+        let (x_offset, y_offset) = match self.anchor {
+            Anchor::LowerLeft => (0.0, 0.0),
+            Anchor::UpperLeft => (0.0, scaled_height),
+            Anchor::Center => ((scaled_width / 2.0).floor(), (scaled_height / 2.0).floor()),
+        };
+
+        let lower_left_x = f32::from(position.x) - x_offset;
+        let lower_left_y = f32::from(position.y) - y_offset;
+
+        // Flipping mirrors the quad around the pivot, so the texture coordinates
+        // are only rotated. Mirroring after the rotation is the same as the
+        // flip of the texture coordinates that follows the rotation in the shader.
+        let mirror_axis_x = lower_left_x + scaled_width / 2.0 + f32::from(self.pivot.x);
+        let mirror_axis_y = lower_left_y + scaled_height / 2.0 + f32::from(self.pivot.y);
+        let mirror_matrix = Matrix4::from_translation(
+            if self.flip_x {
+                2.0 * mirror_axis_x
+            } else {
+                0.0
+            },
+            if self.flip_y {
+                2.0 * mirror_axis_y
+            } else {
+                0.0
+            },
+            0.0,
+        ) * Matrix4::from_scale(
+            if self.flip_x { -1.0 } else { 1.0 },
+            if self.flip_y { -1.0 } else { 1.0 },
+            1.0,
+        );
+
+        mirror_matrix
+            * Matrix4::from_translation(lower_left_x, lower_left_y, 0.0)
+            * Matrix4::from_scale(scaled_width, scaled_height, 1.0)
     }
 }
 
+/// Which edge of each line the text position marks.
+#[derive(Debug, Copy, Clone, Default, PartialEq, Eq)]
+pub enum TextAlign {
+    #[default]
+    Left,
+    Center,
+    Right,
+}
+
+#[derive(Debug, Copy, Clone, Default)]
+pub struct TextParams {
+    pub align: TextAlign,
+    /// Offset and color of a copy of the text that is drawn behind it.
+    pub shadow: Option<(Vec2, Color)>,
+    /// Color of a one pixel outline, drawn as eight offset copies behind the text.
+    pub outline: Option<Color>,
+}
+
 #[derive(Debug, Copy, Clone)]
 pub struct QuadParams {
     pub scale: u8,
@@ -1947,10 +4220,7 @@ impl Display for Texture {
 
 impl PartialOrd<Self> for Texture {
     fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
-        Some(
-            self.texture_and_sampler_bind_group
-                .cmp(&other.texture_and_sampler_bind_group),
-        )
+        Some(self.cmp(other))
     }
 }
 
@@ -1984,6 +4254,28 @@ impl Material {
     pub fn is_complete(&self, textures: &Assets<Texture>) -> bool {
         self.kind.is_complete(textures)
     }
+
+    /// Returns true if the material overwrites the destination, without blending.
+    #[inline]
+    #[must_use]
+    pub const fn is_opaque(&self) -> bool {
+        matches!(
+            self.kind,
+            MaterialKind::NormalSprite {
+                blend_mode: BlendMode::Opaque,
+                ..
+            }
+        )
+    }
+
+    /// Returns true if render items using the two materials can share one instanced draw call.
+    /// It compares the kind and the texture ids, so separately created materials
+    /// for the same texture batch together, even if they are not the same `MaterialRef`.
+    #[inline]
+    #[must_use]
+    pub fn is_batch_compatible(&self, other: &Self) -> bool {
+        std::ptr::eq(self, other) || self.kind == other.kind
+    }
 }
 
 impl Display for Material {
@@ -1992,19 +4284,118 @@ impl Display for Material {
     }
 }
 
+/// A range of instances in the instance buffer that is drawn with one instanced draw call.
+#[derive(Debug)]
+struct Batch {
+    material: WeakMaterialRef,
+    start: u32,
+    count: u32,
+    clip: Option<URect>,
+    layer: LayerId,
+    target: Option<WeakTextureRef>,
+}
+
+/// Where and how the batches are rendered in [`Render::render_batches`].
+struct PassTarget<'a> {
+    color: &'a TextureView,
+    depth: &'a TextureView,
+    load: wgpu::LoadOp<wgpu::Color>,
+    viewport_size: Option<UVec2>,
+    camera_bind_group: &'a BindGroup,
+    label: &'a str,
+}
+
+#[derive(Debug)]
+struct RenderTarget {
+    texture_ref: WeakTextureRef,
+    view: TextureView,
+    depth_view: TextureView,
+    size: UVec2,
+    camera_buffer: Buffer,
+    camera_bind_group: BindGroup,
+}
+
+/// Identifies a render layer, see [`Render::set_layer`]. Items are on layer 0 unless set otherwise.
+///
+/// The layer takes priority over z, so z can be kept for the depth within a layer.
+#[derive(Debug, Default, Copy, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct LayerId(pub u16);
+
+impl LayerId {
+    pub const WORLD: Self = Self(0);
+    pub const LIGHTING: Self = Self(1);
+    pub const UI: Self = Self(2);
+    /// Always drawn last, e.g. for a mouse cursor or a debug overlay.
+    pub const TOP: Self = Self(u16::MAX);
+}
+
+/// Debug visualizations that replace the materials of all quads
+#[derive(Debug, Default, Copy, Clone, PartialEq, Eq)]
+pub enum DebugDraw {
+    #[default]
+    Off,
+    /// Each quad is added with a faint color, so areas that are drawn many times are bright
+    Overdraw,
+    /// Only the triangle edges are drawn. Requires the `POLYGON_MODE_LINE` device feature
+    Wireframe,
+}
+
+/// How a png texture is uploaded and sampled, see [`plugin::TextureOptionsByName`].
+#[derive(Debug, Default, Copy, Clone, PartialEq, Eq)]
+pub struct TextureOptions {
+    /// Multiplies the color channels with alpha when uploading, for
+    /// [`BlendMode::PremultipliedAlpha`].
+    pub premultiply_alpha: bool,
+    /// Creates the full mip chain, so the texture does not shimmer when it is scaled down.
+    /// Use together with [`TextureFilter::Linear`] for trilinear filtering.
+    pub generate_mipmaps: bool,
+    pub filter: TextureFilter,
+}
+
+/// How a texture is sampled when it is scaled
+#[derive(Debug, Default, Copy, Clone, PartialEq, Eq)]
+pub enum TextureFilter {
+    /// Pixel perfect, the right choice for pixel art
+    #[default]
+    Nearest,
+    /// Linear filtering, and between mip levels if the texture has mipmaps
+    Linear,
+}
+
+/// How a sprite is combined with what is already rendered
+#[derive(Debug, Default, Copy, Clone, Ord, PartialOrd, PartialEq, Eq)]
+pub enum BlendMode {
+    #[default]
+    Alpha,
+    Additive,
+    Multiply,
+    /// Ignores the alpha channel and overwrites the destination
+    Opaque,
+    /// Same as `Alpha`, but for textures where the color is already multiplied with alpha
+    PremultipliedAlpha,
+}
+
 #[derive(Debug, Ord, PartialOrd, PartialEq, Eq)]
 pub enum MaterialKind {
     NormalSprite {
         primary_texture: Id<Texture>,
+        blend_mode: BlendMode,
     },
     AlphaMasker {
         primary_texture: Id<Texture>,
         alpha_texture: Id<Texture>,
     },
     Quad,
+    Circle,
+    /// Quad with rounded corners, see [`Render::draw_rounded_quad`].
+    RoundedQuad,
     LightAdd {
         primary_texture: Id<Texture>,
     },
+    /// Sprite with an outline around its opaque texels, see [`Render::draw_sprite_outlined`].
+    Outline {
+        primary_texture: Id<Texture>,
+    },
 }
 
 impl MaterialKind {}
@@ -2017,23 +4408,26 @@ impl MaterialKind {
                 primary_texture, ..
             }
             | Self::LightAdd { primary_texture }
+            | Self::Outline { primary_texture }
             | Self::AlphaMasker {
                 primary_texture, ..
             } => Some(primary_texture.clone()),
-            Self::Quad => None,
+            Self::Quad | Self::Circle | Self::RoundedQuad => None,
         }
     }
 
     pub(crate) fn is_complete(&self, textures: &Assets<Texture>) -> bool {
         match &self {
-            Self::NormalSprite { primary_texture } | Self::LightAdd { primary_texture } => {
-                textures.contains(primary_texture)
+            Self::NormalSprite {
+                primary_texture, ..
             }
+            | Self::LightAdd { primary_texture }
+            | Self::Outline { primary_texture } => textures.contains(primary_texture),
             Self::AlphaMasker {
                 primary_texture,
                 alpha_texture,
             } => textures.contains(primary_texture) && textures.contains(alpha_texture),
-            Self::Quad => true,
+            Self::Quad | Self::Circle | Self::RoundedQuad => true,
         }
     }
 }
@@ -2048,7 +4442,10 @@ impl Display for MaterialKind {
             Self::NormalSprite { .. } => "NormalSprite",
             Self::LightAdd { .. } => "Light (Add)",
             Self::Quad => "Quad",
+            Self::Circle => "Circle",
+            Self::RoundedQuad => "Rounded Quad",
             Self::AlphaMasker { .. } => "AlphaMasker",
+            Self::Outline { .. } => "Outline",
         };
 
         write!(f, "{kind_name} texture {texture_name}")
@@ -2067,6 +4464,43 @@ pub struct QuadColor {
     pub params: QuadParams,
 }
 
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum GradientDirection {
+    /// From the bottom edge to the top edge
+    Vertical,
+    /// From the left edge to the right edge
+    Horizontal,
+}
+
+#[derive(Debug)]
+pub struct QuadGradient {
+    pub size: UVec2,
+    pub start_color: Color,
+    pub end_color: Color,
+    pub direction: GradientDirection,
+}
+
+#[derive(Debug)]
+pub struct Circle {
+    pub radii: UVec2,
+    pub color: Color,
+}
+
+#[derive(Debug)]
+pub struct RoundedQuad {
+    pub size: UVec2,
+    pub radius: u16,
+    pub color: Color,
+}
+
+#[derive(Debug)]
+pub struct Line {
+    /// `to` relative to `from`, `from` is the position of the render item
+    pub delta: Vec2,
+    pub thickness: u16,
+    pub color: Color,
+}
+
 #[derive(Debug, Copy, Clone)]
 pub struct Slices {
     pub left: u16,
@@ -2082,17 +4516,97 @@ pub struct NineSlice {
     pub color: Color, // color tint
     pub origin_in_atlas: UVec2,
     pub size_inside_atlas: Option<UVec2>,
+    pub mode: NineSliceMode,
+    /// If false, only the corners and edges are drawn, e.g. for frames around other content.
+    pub draw_center: bool,
 }
 
+/// How the edges and center of a nine slice fill the space between the fixed size corners.
+#[derive(Debug, Default, Copy, Clone, PartialEq, Eq)]
+pub enum NineSliceMode {
+    /// Repeats the texture, pixel perfect.
+    #[default]
+    Tile,
+    /// Stretches a single quad for each edge and the center.
+    Stretch,
+}
+
+/// Tile flag for flipping the tile horizontally, same meaning as in Tiled.
+pub const TILE_FLIP_X_FLAG: u16 = 0x8000;
+/// Tile flag for flipping the tile vertically, same meaning as in Tiled.
+pub const TILE_FLIP_Y_FLAG: u16 = 0x4000;
+/// Tile flag for flipping the tile over the diagonal (swapping x and y), same meaning as in Tiled.
+/// Combined with the other flags it describes the 90 and 270 degree rotations.
+pub const TILE_FLIP_DIAGONAL_FLAG: u16 = 0x2000;
+/// Mask for the cell index part of a tile.
+pub const TILE_INDEX_MASK: u16 = 0x1FFF;
+/// Tile value for a cell without a tile, nothing is drawn for it. The cell index 8191
+/// (`TILE_INDEX_MASK`) is reserved for this, so a tile with that index is empty whatever
+/// its flip flags are, including `u16::MAX`.
+pub const TILE_EMPTY: u16 = TILE_INDEX_MASK;
+
 #[derive(Debug)]
 pub struct TileMap {
     pub tiles_data_grid_size: UVec2,
     pub cell_count_size: UVec2,
     pub one_cell_size: UVec2,
+    /// Padding in the atlas, see [`FixedAtlas::margin`]
+    pub margin: UVec2,
+    /// Padding in the atlas, see [`FixedAtlas::spacing`]
+    pub spacing: UVec2,
+    /// Cell index in the lower 13 bits and `TILE_FLIP_*_FLAG`s in the upper three.
+    /// Index 8191 is reserved for [`TILE_EMPTY`].
     pub tiles: Vec<u16>,
     pub scale: u8,
 }
 
+impl TileMap {
+    /// The number of rows of `tile_count` tiles in rows of `width`, including a final
+    /// partial row.
+    #[must_use]
+    pub fn grid_height(tile_count: usize, width: u16) -> u16 {
+        if width == 0 {
+            return 0;
+        }
+        u16::try_from(tile_count.div_ceil(usize::from(width))).unwrap_or(u16::MAX)
+    }
+
+    /// Returns the cells, in cell coordinates, that overlap `camera_rect` when the map
+    /// is drawn at `position`. The size is zero if no cell is visible.
+    #[must_use]
+    pub fn visible_range(&self, position: Vec2, camera_rect: Rect) -> URect {
+        let cell_width = i32::from(self.one_cell_size.x) * i32::from(self.scale);
+        let cell_height = i32::from(self.one_cell_size.y) * i32::from(self.scale);
+        if cell_width == 0 || cell_height == 0 {
+            return URect::new(0, 0, 0, 0);
+        }
+
+        let left = i32::from(camera_rect.position.x) - i32::from(position.x);
+        let bottom = i32::from(camera_rect.position.y) - i32::from(position.y);
+        let right = left + i32::from(camera_rect.size.x);
+        let top = bottom + i32::from(camera_rect.size.y);
+
+        let grid_width = i32::from(self.tiles_data_grid_size.x);
+        let grid_height = i32::from(self.tiles_data_grid_size.y);
+
+        let min_x = left.div_euclid(cell_width).clamp(0, grid_width);
+        let min_y = bottom.div_euclid(cell_height).clamp(0, grid_height);
+        let max_x = (right + cell_width - 1)
+            .div_euclid(cell_width)
+            .clamp(min_x, grid_width);
+        let max_y = (top + cell_height - 1)
+            .div_euclid(cell_height)
+            .clamp(min_y, grid_height);
+
+        URect::new(
+            min_x as u16,
+            min_y as u16,
+            (max_x - min_x) as u16,
+            (max_y - min_y) as u16,
+        )
+    }
+}
+
 #[derive(PartialEq, Debug, Eq, Ord, PartialOrd)]
 pub struct Pipeline {
     name: String,
@@ -2106,3 +4620,292 @@ impl Display for Pipeline {
 }
 
 pub type PipelineRef = Arc<Pipeline>;
+
+const fn rect_from_urect(rect: URect) -> Rect {
+    Rect::new(
+        rect.position.x as i16,
+        rect.position.y as i16,
+        rect.size.x,
+        rect.size.y,
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn quad_item(id: u16, z: i16) -> RenderItem {
+        RenderItem {
+            position: Vec3::new(0, 0, z),
+            material_ref: quad_material(),
+            clip: None,
+            layer: LayerId::default(),
+            target: None,
+            renderable: Renderable::QuadColor(QuadColor {
+                size: UVec2::new(id, id),
+                color: Color::from_octet(255, 255, 255, 255),
+                params: QuadParams::default(),
+            }),
+        }
+    }
+
+    fn quad_ids(items: &[RenderItem]) -> Vec<u16> {
+        items
+            .iter()
+            .map(|item| match &item.renderable {
+                Renderable::QuadColor(quad) => quad.size.x,
+                _ => unreachable!(),
+            })
+            .collect()
+    }
+
+    /// Where the unit quad corner `(x, y)` ends up.
+    fn transform(matrix: Matrix4, x: f32, y: f32) -> (f32, f32) {
+        let column = |index: usize| matrix[index].0;
+        (
+            column(0)[0] * x + column(1)[0] * y + column(3)[0],
+            column(0)[1] * x + column(1)[1] * y + column(3)[1],
+        )
+    }
+
+    /// The lower left and upper right corners of the transformed unit quad.
+    fn quad_bounds(matrix: Matrix4) -> ((f32, f32), (f32, f32)) {
+        let (x0, y0) = transform(matrix, 0.0, 0.0);
+        let (x1, y1) = transform(matrix, 1.0, 1.0);
+        ((x0.min(x1), y0.min(y1)), (x0.max(x1), y0.max(y1)))
+    }
+
+    #[test]
+    fn upper_left_anchor_keeps_top_edge_when_scaled() {
+        for scale in [1, 2, 3] {
+            let params = SpriteParams {
+                anchor: Anchor::UpperLeft,
+                scale,
+                ..SpriteParams::default()
+            };
+            let matrix = params.model_matrix(Vec2::new(10, 100), UVec2::new(16, 24));
+            let (lower_left, upper_right) = quad_bounds(matrix);
+            assert_eq!(upper_right.1, 100.0);
+            assert_eq!(lower_left.0, 10.0);
+            assert_eq!(lower_left.1, 100.0 - 24.0 * f32::from(scale));
+        }
+
+        let params = SpriteParams {
+            anchor: Anchor::UpperLeft,
+            scale_f32: Some(ScaleF32::new(0.5, 1.5)),
+            ..SpriteParams::default()
+        };
+        let (lower_left, upper_right) =
+            quad_bounds(params.model_matrix(Vec2::new(10, 100), UVec2::new(16, 24)));
+        assert_eq!(upper_right, (18.0, 100.0));
+        assert_eq!(lower_left, (10.0, 64.0));
+    }
+
+    fn quad_material() -> MaterialRef {
+        MaterialRef::from(Material {
+            base: MaterialBase {},
+            kind: MaterialKind::Quad,
+        })
+    }
+
+    #[test]
+    fn fixed_atlas_cell_count_with_margin_and_spacing() {
+        let atlas = FixedAtlas::new(
+            UVec2::new(16, 16),
+            UVec2::new(128, 64),
+            UVec2::new(1, 1),
+            UVec2::new(2, 2),
+            quad_material(),
+        );
+        assert_eq!(atlas.cell_count_size, UVec2::new(7, 3));
+    }
+
+    #[test]
+    #[should_panic(expected = "no cells")]
+    fn fixed_atlas_margin_larger_than_texture() {
+        let _ = FixedAtlas::new(
+            UVec2::new(16, 16),
+            UVec2::new(64, 64),
+            UVec2::new(40_000, 0),
+            UVec2::new(0, 0),
+            quad_material(),
+        );
+    }
+
+    #[test]
+    fn half_floats_convert_to_f32() {
+        assert_eq!(f16_to_f32(0x0000), 0.0);
+        assert_eq!(f16_to_f32(0x3c00), 1.0);
+        assert_eq!(f16_to_f32(0x3800), 0.5);
+        assert_eq!(f16_to_f32(0xc000), -2.0);
+        assert_eq!(f16_to_f32(0x7bff), 65504.0);
+        assert_eq!(f16_to_f32(0x0001), 2.0_f32.powi(-24));
+        assert_eq!(f16_to_f32(0x7c00), f32::INFINITY);
+        assert!(f16_to_f32(0x7e00).is_nan());
+    }
+
+    #[test]
+    fn pixel_snap_rounds_sprite_and_camera_translations() {
+        // Lower left corner at (10.0, 12.5), seen through a camera at a fractional offset
+        let params = SpriteParams {
+            anchor: Anchor::UpperLeft,
+            scale_f32: Some(ScaleF32::new(1.5, 1.5)),
+            ..SpriteParams::default()
+        };
+        let model_matrix = params.model_matrix(Vec2::new(10, 20), UVec2::new(5, 5));
+        let camera_matrix = Matrix4::from_translation(-100.25, -50.75, 0.0);
+
+        assert_eq!(
+            transform(camera_matrix * model_matrix, 0.0, 0.0),
+            (-90.25, -38.25)
+        );
+
+        let snapped_model_matrix = snap_translation(model_matrix);
+        let snapped = snap_translation(camera_matrix) * snapped_model_matrix;
+        assert_eq!(transform(snapped, 0.0, 0.0), (-90.0, -38.0));
+
+        // Only the translation is snapped, the size is kept
+        assert_eq!(
+            quad_bounds(snapped_model_matrix),
+            ((10.0, 13.0), (17.5, 20.5))
+        );
+    }
+
+    #[test]
+    fn flips_mirror_the_rotated_quad_in_place() {
+        let rotations = [
+            (Rotation::Degrees0, (16.0, 8.0)),
+            (Rotation::Degrees90, (8.0, 16.0)),
+            (Rotation::Degrees180, (16.0, 8.0)),
+            (Rotation::Degrees270, (8.0, 16.0)),
+        ];
+        for (rotation, (width, height)) in rotations {
+            for (flip_x, flip_y) in [(false, false), (true, false), (false, true), (true, true)] {
+                let params = SpriteParams {
+                    rotation,
+                    flip_x,
+                    flip_y,
+                    ..SpriteParams::default()
+                };
+                let matrix = params.model_matrix(Vec2::new(10, 20), UVec2::new(16, 8));
+
+                // Mirrored around the center, so the quad covers the same area
+                assert_eq!(
+                    quad_bounds(matrix),
+                    ((10.0, 20.0), (10.0 + width, 20.0 + height)),
+                    "{rotation:?} flip_x:{flip_x} flip_y:{flip_y}"
+                );
+
+                // The first corner of the unit quad moves to the opposite edge for each flip
+                let expected_x = if flip_x { 10.0 + width } else { 10.0 };
+                let expected_y = if flip_y { 20.0 + height } else { 20.0 };
+                assert_eq!(
+                    transform(matrix, 0.0, 0.0),
+                    (expected_x, expected_y),
+                    "{rotation:?} flip_x:{flip_x} flip_y:{flip_y}"
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn flips_mirror_around_the_pivot() {
+        let params = SpriteParams {
+            rotation: Rotation::Degrees90,
+            flip_x: true,
+            flip_y: true,
+            pivot: Vec2::new(4, -2),
+            ..SpriteParams::default()
+        };
+        let matrix = params.model_matrix(Vec2::new(10, 20), UVec2::new(16, 8));
+        // The 8x16 quad is mirrored around (10 + 4 + 4, 20 + 8 - 2)
+        assert_eq!(quad_bounds(matrix), ((18.0, 16.0), (26.0, 32.0)));
+    }
+
+    #[test]
+    fn sort_keeps_push_order_for_equal_z() {
+        let mut items: Vec<_> = (0..64).map(|id| quad_item(id, 0)).collect();
+        sort_render_items_by_z_and_material(&mut items, false);
+        assert_eq!(quad_ids(&items), (0..64).collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn sort_orders_on_z_then_push_order() {
+        let mut items = vec![
+            quad_item(0, 5),
+            quad_item(1, -1),
+            quad_item(2, 5),
+            quad_item(3, 0),
+            quad_item(4, -1),
+            quad_item(5, 5),
+        ];
+        sort_render_items_by_z_and_material(&mut items, false);
+        assert_eq!(quad_ids(&items), vec![1, 4, 3, 0, 2, 5]);
+    }
+
+    #[test]
+    fn tile_map_visible_range_of_large_map() {
+        let tiles = vec![1; 512 * 512];
+        let tile_map = TileMap {
+            tiles_data_grid_size: UVec2::new(512, TileMap::grid_height(tiles.len(), 512)),
+            cell_count_size: UVec2::new(8, 8),
+            one_cell_size: UVec2::new(16, 16),
+            margin: UVec2::new(0, 0),
+            spacing: UVec2::new(0, 0),
+            tiles,
+            scale: 1,
+        };
+        assert_eq!(tile_map.tiles_data_grid_size, UVec2::new(512, 512));
+
+        let visible = tile_map.visible_range(Vec2::new(0, 0), Rect::new(1000, 2000, 320, 240));
+        assert_eq!(visible.position, UVec2::new(62, 125));
+        assert_eq!(visible.size, UVec2::new(21, 15));
+
+        // Past the last row and column of the 8192x8192 pixel map
+        let visible = tile_map.visible_range(Vec2::new(-8000, -8000), Rect::new(0, 0, 320, 240));
+        assert_eq!(visible.position, UVec2::new(500, 500));
+        assert_eq!(visible.size, UVec2::new(12, 12));
+    }
+
+    #[test]
+    fn tile_map_grid_height_includes_partial_row() {
+        assert_eq!(TileMap::grid_height(12, 4), 3);
+        assert_eq!(TileMap::grid_height(13, 4), 4);
+        assert_eq!(TileMap::grid_height(3, 4), 1);
+        assert_eq!(TileMap::grid_height(0, 4), 0);
+        assert_eq!(TileMap::grid_height(12, 0), 0);
+    }
+
+    #[test]
+    fn scissor_from_clip_inside_target() {
+        let scissor = Render::scissor_from_clip(URect::new(10, 20, 30, 40), UVec2::new(320, 240));
+        assert_eq!(scissor, Some((10, 180, 30, 40)));
+    }
+
+    #[test]
+    fn scissor_from_clip_clamps_to_target() {
+        let scissor =
+            Render::scissor_from_clip(URect::new(300, 200, 100, 100), UVec2::new(320, 240));
+        assert_eq!(scissor, Some((300, 0, 20, 40)));
+    }
+
+    #[test]
+    fn scissor_from_clip_outside_target() {
+        let target_size = UVec2::new(320, 240);
+        assert_eq!(
+            Render::scissor_from_clip(URect::new(0, 300, 10, 10), target_size),
+            None
+        );
+        assert_eq!(
+            Render::scissor_from_clip(URect::new(400, 0, 10, 10), target_size),
+            None
+        );
+        assert_eq!(
+            Render::scissor_from_clip(
+                URect::new(u16::MAX, u16::MAX, u16::MAX, u16::MAX),
+                target_size
+            ),
+            None
+        );
+    }
+}
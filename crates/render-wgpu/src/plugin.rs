@@ -2,18 +2,21 @@
  * Copyright (c) Peter Bjorklund. All rights reserved. https://github.com/mireforge/mireforge
  * Licensed under the MIT License. See LICENSE in the project root for license information.
  */
-use crate::{Render, Texture};
+use crate::{Render, Texture, TextureOptions, TextureRef};
+use int_math::UVec2;
 use limnus_app::prelude::{App, Plugin};
 use limnus_assets::prelude::Assets as LimnusAssets;
 use limnus_clock::Clock;
 use limnus_default_stages::{RenderFirst, RenderPostUpdate};
+use limnus_resource::prelude::Resource;
 use limnus_screen::{Window, WindowMessage};
-use limnus_system_params::{LoRe, Msg, Re, ReM};
-use limnus_wgpu_window::WgpuWindow;
-use mireforge_font::Font;
+use limnus_system_params::{LoRe, LoReM, Msg, Re, ReM};
+use limnus_wgpu_window::{BasicDeviceInfo, WgpuWindow};
+use mireforge_font::{Font, FontRef};
 use monotonic_time_rs::Millis;
+use std::collections::HashMap;
 use std::sync::Arc;
-use tracing::debug;
+use tracing::{debug, info, warn};
 
 fn tick(mut wgpu_render: ReM<Render>, window_messages: Msg<WindowMessage>) {
     for msg in window_messages.iter_previous() {
@@ -24,11 +27,68 @@ fn tick(mut wgpu_render: ReM<Render>, window_messages: Msg<WindowMessage>) {
     }
 }
 
-/// # Panics
-///
+/// Picks `requested` if the surface supports it, otherwise the closest supported mode.
+fn supported_present_mode(
+    requested: wgpu::PresentMode,
+    supported: &[wgpu::PresentMode],
+) -> wgpu::PresentMode {
+    use wgpu::PresentMode;
+    let preferred: &[PresentMode] = match requested {
+        PresentMode::Immediate | PresentMode::AutoNoVsync => {
+            &[PresentMode::Immediate, PresentMode::Mailbox]
+        }
+        PresentMode::Mailbox => &[PresentMode::Mailbox, PresentMode::Immediate],
+        PresentMode::FifoRelaxed => &[PresentMode::FifoRelaxed],
+        PresentMode::Fifo | PresentMode::AutoVsync => &[],
+    };
+    preferred
+        .iter()
+        .copied()
+        .find(|mode| supported.contains(mode))
+        .unwrap_or(PresentMode::Fifo)
+}
+
+fn configure_surface(
+    device_info: &BasicDeviceInfo,
+    format: wgpu::TextureFormat,
+    size: UVec2,
+    requested: wgpu::PresentMode,
+) {
+    if size.x == 0 || size.y == 0 {
+        return;
+    }
+    let caps = device_info.surface.get_capabilities(&device_info.adapter);
+    let present_mode = supported_present_mode(requested, &caps.present_modes);
+    if present_mode != requested {
+        warn!(
+            ?requested,
+            ?present_mode,
+            "present mode is not supported, using fallback"
+        );
+    }
+    debug!(?present_mode, "configuring surface");
+    device_info.surface.configure(
+        &device_info.device,
+        &wgpu::SurfaceConfiguration {
+            usage: wgpu::TextureUsages::RENDER_ATTACHMENT,
+            format,
+            width: u32::from(size.x),
+            height: u32::from(size.y),
+            present_mode,
+            alpha_mode: caps.alpha_modes[0],
+            desired_maximum_frame_latency: 2,
+            view_formats: vec![],
+        },
+    );
+}
+
+/// A frame that could not get a surface texture is skipped, and the surface is configured
+/// again so the next frame can recover. `Lost` and `Outdated` are already skipped by
+/// [`WgpuWindow::render`] and fatal errors (out of memory) exit there.
 pub fn flush_render_tick(
     script: LoRe<Clock>,
-    wgpu_window: LoRe<WgpuWindow>,
+    mut wgpu_window: LoReM<WgpuWindow>,
+    device_info: LoRe<BasicDeviceInfo>,
     mut wgpu_render: ReM<Render>,
     //materials: Re<LimnusAssets<Material>>,
     textures: Re<LimnusAssets<Texture>>,
@@ -36,30 +96,178 @@ pub fn flush_render_tick(
 ) {
     let now = script.clock.now();
 
-    wgpu_window
-        .render(|encoder, texture_view| {
-            wgpu_render.render(encoder, texture_view, &textures, &fonts, now);
-        })
-        .unwrap();
+    if let Some(present_mode) = wgpu_render.take_surface_configure() {
+        configure_surface(
+            &device_info,
+            wgpu_window.texture_format(),
+            wgpu_render.physical_surface_size(),
+            present_mode,
+        );
+    }
+
+    let result = wgpu_window.render(|encoder, texture_view| {
+        wgpu_render.render(encoder, texture_view, &textures, &fonts, now);
+    });
+
+    if let Err(err) = result {
+        if !needs_surface_configure(&err) {
+            warn!(?err, "skipping frame");
+            return;
+        }
+        warn!(?err, "skipping frame, configuring the surface again");
+        wgpu_window.configure_surface();
+        if wgpu_render.present_mode() != wgpu::PresentMode::Fifo {
+            configure_surface(
+                &device_info,
+                wgpu_window.texture_format(),
+                wgpu_render.physical_surface_size(),
+                wgpu_render.present_mode(),
+            );
+        }
+    }
 }
+
+/// The surface has to be configured again after it is outdated or lost, e.g. after a resize or
+/// when the display changed. A timeout is only retried on the next frame.
+const fn needs_surface_configure(err: &wgpu::SurfaceError) -> bool {
+    match err {
+        wgpu::SurfaceError::Outdated | wgpu::SurfaceError::Lost | wgpu::SurfaceError::Other => true,
+        wgpu::SurfaceError::Timeout | wgpu::SurfaceError::OutOfMemory => false,
+    }
+}
+
+/// Rasterized fonts and the texture that their glyph atlas is uploaded to.
+#[derive(Debug, Default, Resource)]
+pub struct FontAtlases {
+    atlases: Vec<(FontRef, TextureRef)>,
+}
+
+impl FontAtlases {
+    pub fn push(&mut self, font_ref: FontRef, texture_ref: TextureRef) {
+        self.atlases.push((font_ref, texture_ref));
+    }
+}
+
+/// Uploads the glyph atlas of fonts that have been rasterized (or reloaded) since the last frame.
+fn upload_font_atlases(
+    wgpu_render: Re<Render>,
+    font_atlases: Re<FontAtlases>,
+    mut fonts: ReM<LimnusAssets<Font>>,
+    mut textures: ReM<LimnusAssets<Texture>>,
+) {
+    for (font_ref, texture_ref) in &font_atlases.atlases {
+        let Some(atlas) = fonts.get_mut(font_ref).and_then(Font::take_atlas) else {
+            continue;
+        };
+        let texture = wgpu_render.texture_from_rgba(&atlas.rgba, atlas.size, "font atlas");
+        textures.set(texture_ref, texture);
+    }
+}
+
+/// The [`TextureOptions`] that png textures are loaded with, by asset name. Textures without an
+/// entry use the defaults. The entries are kept, so a reloaded texture gets the same options.
+#[derive(Debug, Default, Resource)]
+pub struct TextureOptionsByName {
+    options: HashMap<String, TextureOptions>,
+}
+
+impl TextureOptionsByName {
+    pub fn insert(&mut self, name: &str, options: TextureOptions) {
+        self.options.insert(name.to_string(), options);
+    }
+
+    #[must_use]
+    pub fn get(&self, name: &str) -> TextureOptions {
+        self.options.get(name).copied().unwrap_or_default()
+    }
+}
+
+/// The adapter that is used for rendering, e.g. for a debug overlay or bug reports.
+#[derive(Debug, Clone, Resource)]
+pub struct GpuInfo {
+    pub name: String,
+    pub backend: wgpu::Backend,
+    pub device_type: wgpu::DeviceType,
+    pub driver: String,
+    pub max_texture_dimension: u32,
+}
+
+impl GpuInfo {
+    #[must_use]
+    pub fn new(adapter: &wgpu::Adapter) -> Self {
+        let adapter_info = adapter.get_info();
+        Self {
+            name: adapter_info.name,
+            backend: adapter_info.backend,
+            device_type: adapter_info.device_type,
+            driver: adapter_info.driver,
+            max_texture_dimension: adapter.limits().max_texture_dimension_2d,
+        }
+    }
+}
+
+/// Insert before [`RenderWgpuPlugin`] to render the virtual surface in another format than
+/// the window surface, e.g. `Rgba16Float` for light accumulation without banding.
+#[derive(Debug, Resource)]
+pub struct VirtualTextureFormat(pub wgpu::TextureFormat);
+
 pub struct RenderWgpuPlugin;
 
 impl Plugin for RenderWgpuPlugin {
     fn post_initialization(&self, app: &mut App) {
+        // The adapter is picked by `limnus-wgpu-window`, high performance first
+        let gpu_info = app
+            .local_resources()
+            .get::<BasicDeviceInfo>()
+            .map(|device_info| GpuInfo::new(&device_info.adapter));
+        if let Some(gpu_info) = gpu_info {
+            info!(
+                name = gpu_info.name,
+                backend = ?gpu_info.backend,
+                device_type = ?gpu_info.device_type,
+                "using gpu adapter"
+            );
+            app.insert_resource(gpu_info);
+        }
+
         let window = app.local_resources().fetch::<WgpuWindow>();
         let window_settings = app.resource::<Window>();
-        let wgpu_render = Render::new(
+        let virtual_texture_format = app
+            .get_resource_ref::<VirtualTextureFormat>()
+            .map_or_else(|| window.texture_format(), |format| format.0);
+        let wgpu_render = Render::new_with_virtual_texture_format(
             Arc::clone(window.device()),
             Arc::clone(window.queue()),
             window.texture_format(),
+            virtual_texture_format,
             window_settings.requested_surface_size,
             window_settings.minimal_surface_size,
             Millis::new(0),
         );
 
         app.insert_resource(wgpu_render);
+        app.insert_resource(FontAtlases::default());
+        app.insert_resource(TextureOptionsByName::default());
 
         app.add_system(RenderFirst, tick);
+        app.add_system(RenderFirst, upload_font_atlases);
         app.add_system(RenderPostUpdate, flush_render_tick);
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn outdated_and_lost_surfaces_are_configured_again() {
+        assert!(needs_surface_configure(&wgpu::SurfaceError::Outdated));
+        assert!(needs_surface_configure(&wgpu::SurfaceError::Lost));
+        assert!(needs_surface_configure(&wgpu::SurfaceError::Other));
+    }
+
+    #[test]
+    fn timeouts_skip_the_frame() {
+        assert!(!needs_surface_configure(&wgpu::SurfaceError::Timeout));
+    }
+}
@@ -3,6 +3,10 @@
  * Licensed under the MIT License. See LICENSE in the project root for license information.
  */
 pub use crate::{
-    Anchor, FixedAtlas, FontAndMaterial, FrameLookup, Material, MaterialRef, NineSliceAndMaterial,
-    Render, Rotation, Slices, SpriteParams, TextureRef, gfx::Gfx, plugin::RenderWgpuPlugin,
+    Anchor, AnimationClip, AnimationState, Atlas, BlendMode, DebugDraw, FixedAtlas,
+    FontAndMaterial, FrameLookup, INSTANCE_BUFFER_COUNT, LayerId, Material, MaterialRef,
+    NineSliceAndMaterial, NineSliceMode, QuadLimits, Render, RenderStats, Rotation, ScaleF32,
+    Slices, SpriteParams, TILE_EMPTY, TILE_FLIP_DIAGONAL_FLAG, TILE_FLIP_X_FLAG, TILE_FLIP_Y_FLAG,
+    TILE_INDEX_MASK, TextAlign, TextParams, TextureFilter, TextureOptions, TextureRef, gfx::Gfx,
+    plugin::GpuInfo, plugin::RenderWgpuPlugin, plugin::VirtualTextureFormat,
 };
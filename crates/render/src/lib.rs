@@ -133,6 +133,18 @@ impl Color {
     }
 }
 
+/// Effect applied to the whole virtual surface when it is drawn to the screen.
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub enum ScreenEffect {
+    #[default]
+    None,
+    /// `saturation` of 0.0 is grayscale and 1.0 is the original colors.
+    /// `brightness` multiplies the resulting color.
+    Desaturate { saturation: f32, brightness: f32 },
+    /// Scanlines and a slight barrel distortion. `intensity` of 0.0 is off and 1.0 is full strength.
+    Crt { intensity: f32 },
+}
+
 #[derive(Debug, Eq, PartialEq)]
 pub enum ViewportStrategy {
     /// Tries to set the viewport to fit the virtual surface size within the physical surface size.
@@ -145,6 +157,11 @@ pub enum ViewportStrategy {
     /// Always keeps the aspect ratio, but might not be pixel perfect
     FitFloatScaling,
 
+    /// Scales the virtual surface with the smallest integer scale that covers the whole
+    /// physical surface. There are no borders, instead the edges of the virtual surface
+    /// that do not fit are cropped. Always keeps the aspect ratio, and is "pixel perfect"
+    FillCropInteger,
+
     /// The viewport will be the same as the physical size.
     MatchPhysicalSize,
 }
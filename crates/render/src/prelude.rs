@@ -3,6 +3,6 @@
  * Licensed under the MIT License. See LICENSE in the project root for license information.
  */
 pub use crate::{
-    AspectRatio, Color, ViewportStrategy, VirtualScale,
+    AspectRatio, Color, ScreenEffect, ViewportStrategy, VirtualScale,
     anim::{AnimationLookup, FrameAnimation, FrameAnimationConfig},
 };
@@ -23,6 +23,7 @@ use wgpu::{
     PrimitiveState, PrimitiveTopology, util,
 };
 use wgpu::{BufferBindingType, TextureView};
+use wgpu::{CompareFunction, DepthStencilState};
 
 #[repr(C)]
 #[derive(Copy, Clone, Debug)]
@@ -62,6 +63,8 @@ pub fn create_sprite_uniform_buffer(device: &Device, label: &str) -> Buffer {
             tex_coords_mul_add: Vec4([0.0, 0.0, 1.0, 1.0]),
             rotation: 0,
             color: Vec4([1.0, 0.0, 1.0, 1.0]),
+            secondary_color: Vec4([1.0, 0.0, 1.0, 1.0]),
+            add_color: Vec4([0.0; 4]),
         }]),
         usage: BufferUsages::UNIFORM | BufferUsages::COPY_DST,
     })
@@ -76,6 +79,35 @@ pub struct CameraUniform {
 unsafe impl Pod for CameraUniform {}
 unsafe impl Zeroable for CameraUniform {}
 
+/// Parameters for the virtual to screen shader.
+#[repr(C)]
+#[derive(Copy, Clone)]
+pub struct ScreenEffectUniform {
+    pub saturation: f32,
+    pub brightness: f32,
+    pub crt_intensity: f32,
+    /// Seconds since start, used for animating the CRT scanlines
+    pub time: f32,
+    pub virtual_size: [f32; 2],
+    pub padding: [f32; 2],
+}
+
+unsafe impl Pod for ScreenEffectUniform {}
+unsafe impl Zeroable for ScreenEffectUniform {}
+
+impl Default for ScreenEffectUniform {
+    fn default() -> Self {
+        Self {
+            saturation: 1.0,
+            brightness: 1.0,
+            crt_intensity: 0.0,
+            time: 0.0,
+            virtual_size: [1.0; 2],
+            padding: [0.0; 2],
+        }
+    }
+}
+
 #[repr(C)]
 #[derive(Copy, Clone)]
 pub struct SpriteInstanceUniform {
@@ -88,12 +120,21 @@ pub struct SpriteInstanceUniform {
     pub tex_coords_mul_add: Vec4,
     pub rotation: u32,
     pub color: Vec4,
+    /// For sprites, the flash color in `rgb` and how much to flash in `a`.
+    /// For outlined sprites, the outline color.
+    /// For quads, the color at the end of a gradient.
+    /// For alpha masks, the mask texture coordinate offset in `xy`.
+    /// For rounded quads, the size in `xy` and the corner radius in `z`.
+    pub secondary_color: Vec4,
+    /// Added to the tinted texture color of sprites (only `rgb`). Zero for everything else.
+    pub add_color: Vec4,
 }
 
 unsafe impl Pod for SpriteInstanceUniform {}
 unsafe impl Zeroable for SpriteInstanceUniform {}
 
 impl SpriteInstanceUniform {
+    /// The secondary color is zero, which means no flash for sprites.
     #[must_use]
     pub const fn new(model: Matrix4, tex_coords_mul_add: Vec4, rotation: u32, color: Vec4) -> Self {
         Self {
@@ -101,6 +142,27 @@ impl SpriteInstanceUniform {
             tex_coords_mul_add,
             rotation,
             color,
+            secondary_color: Vec4([0.0; 4]),
+            add_color: Vec4([0.0; 4]),
+        }
+    }
+
+    /// For quads, `rotation` is the gradient direction, 0 for vertical and 1 for horizontal.
+    #[must_use]
+    pub const fn new_ex(
+        model: Matrix4,
+        tex_coords_mul_add: Vec4,
+        rotation: u32,
+        color: Vec4,
+        secondary_color: Vec4,
+    ) -> Self {
+        Self {
+            model,
+            tex_coords_mul_add,
+            rotation,
+            color,
+            secondary_color,
+            add_color: Vec4([0.0; 4]),
         }
     }
 }
@@ -150,6 +212,18 @@ impl SpriteInstanceUniform {
                     shader_location: 8,
                     format: VertexFormat::Float32x4,
                 },
+                // secondary color (RGBA)
+                VertexAttribute {
+                    offset: 100,
+                    shader_location: 9,
+                    format: VertexFormat::Float32x4,
+                },
+                // additive color (RGBA)
+                VertexAttribute {
+                    offset: 116,
+                    shader_location: 10,
+                    format: VertexFormat::Float32x4,
+                },
             ],
         }
     }
@@ -187,10 +261,21 @@ pub const INDICES: &[u16] = &[0, 1, 2, 0, 2, 3];
 #[derive(Debug)]
 pub struct SpriteInfo {
     pub sprite_shader_info: ShaderInfo,
+    pub sprite_multiply_shader_info: ShaderInfo,
+    pub sprite_premultiplied_shader_info: ShaderInfo,
+    pub sprite_outline_shader_info: ShaderInfo,
+    pub sprite_opaque_shader_info: ShaderInfo,
     pub quad_shader_info: ShaderInfo,
+    pub circle_shader_info: ShaderInfo,
+    pub rounded_quad_shader_info: ShaderInfo,
     pub mask_shader_info: ShaderInfo,
     pub light_shader_info: ShaderInfo,
     pub virtual_to_screen_shader_info: ShaderInfo,
+    pub crt_screen_shader_info: ShaderInfo,
+
+    // Screen effect - Group 1 of the virtual to screen shader
+    pub screen_effect_uniform_buffer: Buffer,
+    pub screen_effect_bind_group: BindGroup,
 
     pub sampler: Sampler,
     pub vertex_buffer: Buffer,
@@ -211,6 +296,38 @@ pub struct SpriteInfo {
 
 const MAX_RENDER_SPRITE_COUNT: usize = 10_000;
 
+/// Format of the depth texture that the sprite pipelines are created for.
+pub const DEPTH_TEXTURE_FORMAT: TextureFormat = TextureFormat::Depth32Float;
+
+/// Depth state for the pipelines that render to the virtual surface. The depth test always
+/// passes for equal depth, so when everything is rendered at the same depth, the draw order decides.
+#[must_use]
+pub fn sprite_depth_stencil_state(depth_write_enabled: bool) -> DepthStencilState {
+    DepthStencilState {
+        format: DEPTH_TEXTURE_FORMAT,
+        depth_write_enabled,
+        depth_compare: CompareFunction::LessEqual,
+        stencil: wgpu::StencilState::default(),
+        bias: wgpu::DepthBiasState::default(),
+    }
+}
+
+#[must_use]
+pub fn create_depth_texture_view(device: &Device, size: Extent3d, label: &str) -> TextureView {
+    let texture = device.create_texture(&TextureDescriptor {
+        label: Some(label),
+        size,
+        mip_level_count: 1,
+        sample_count: 1,
+        dimension: TextureDimension::D2,
+        format: DEPTH_TEXTURE_FORMAT,
+        usage: TextureUsages::RENDER_ATTACHMENT,
+        view_formats: &[],
+    });
+
+    texture.create_view(&TextureViewDescriptor::default())
+}
+
 #[derive(Debug)]
 pub struct ShaderInfo {
     pub vertex_shader: ShaderModule,
@@ -234,6 +351,9 @@ pub fn create_shader_info(
     layouts.push(camera_bind_group_layout);
     layouts.extend_from_slice(specific_layouts);
 
+    // Only the opaque (replacing) pipelines write depth, blended content is just tested against it
+    let depth_stencil = sprite_depth_stencil_state(blend_state == BlendState::REPLACE);
+
     create_shader_info_ex(
         device,
         surface_texture_format,
@@ -242,6 +362,7 @@ pub fn create_shader_info(
         fragment_source,
         &[Vertex::desc(), SpriteInstanceUniform::desc()],
         blend_state,
+        Some(depth_stencil),
         name,
     )
 }
@@ -256,6 +377,7 @@ pub fn create_shader_info_ex(
     fragment_source: &str,
     buffers: &[VertexBufferLayout],
     blend_state: BlendState,
+    depth_stencil: Option<DepthStencilState>,
     name: &str,
 ) -> ShaderInfo {
     let vertex_shader =
@@ -274,6 +396,57 @@ pub fn create_shader_info_ex(
         &fragment_shader,
         buffers,
         blend_state,
+        depth_stencil,
+        PolygonMode::Fill,
+        name,
+    );
+
+    ShaderInfo {
+        vertex_shader,
+        fragment_shader,
+        pipeline,
+    }
+}
+
+/// Pipeline that draws every quad, whatever its material, with a constant color and
+/// additive blending. With `PolygonMode::Fill`, areas that are drawn many times show up bright
+/// (overdraw), with `PolygonMode::Line` only the triangle edges are drawn (wireframe).
+/// `PolygonMode::Line` requires the `POLYGON_MODE_LINE` device feature.
+#[must_use]
+pub fn create_debug_shader_info(
+    device: &Device,
+    surface_texture_format: TextureFormat,
+    camera_bind_group_layout: &BindGroupLayout,
+    polygon_mode: PolygonMode,
+    name: &str,
+) -> ShaderInfo {
+    let (vertex_source, _) = quad_shaders();
+    let fragment_source = if polygon_mode == PolygonMode::Fill {
+        overdraw_fragment_source()
+    } else {
+        wireframe_fragment_source()
+    };
+    let vertex_shader =
+        mireforge_wgpu::create_shader_module(device, &format!("{name} vertex"), vertex_source);
+    let fragment_shader =
+        mireforge_wgpu::create_shader_module(device, &format!("{name} fragment"), fragment_source);
+
+    let custom_layout = create_pipeline_layout(
+        device,
+        &[camera_bind_group_layout],
+        &format!("{name} pipeline layout"),
+    );
+
+    let pipeline = create_pipeline_with_buffers(
+        device,
+        surface_texture_format,
+        &custom_layout,
+        &vertex_shader,
+        &fragment_shader,
+        &[Vertex::desc(), SpriteInstanceUniform::desc()],
+        additive_blend_state(),
+        Some(sprite_depth_stencil_state(false)),
+        polygon_mode,
         name,
     );
 
@@ -290,6 +463,7 @@ impl SpriteInfo {
     pub fn new(
         device: &Device,
         surface_texture_format: TextureFormat,
+        virtual_texture_format: TextureFormat,
         view_proj_matrix: Matrix4,
     ) -> Self {
         let index_buffer = create_sprite_index_buffer(device, "identity quad index buffer");
@@ -322,7 +496,7 @@ impl SpriteInfo {
 
         let sprite_shader_info = create_shader_info(
             device,
-            surface_texture_format,
+            virtual_texture_format,
             &camera_bind_group_layout,
             &[&sprite_texture_sampler_bind_group_layout],
             sprite_vertex_shader_source,
@@ -331,13 +505,57 @@ impl SpriteInfo {
             "Sprite",
         );
 
+        let sprite_multiply_shader_info = create_shader_info(
+            device,
+            virtual_texture_format,
+            &camera_bind_group_layout,
+            &[&sprite_texture_sampler_bind_group_layout],
+            sprite_vertex_shader_source,
+            sprite_fragment_shader_source,
+            multiply_blend_state(),
+            "Sprite (Multiply)",
+        );
+
+        let sprite_premultiplied_shader_info = create_shader_info(
+            device,
+            virtual_texture_format,
+            &camera_bind_group_layout,
+            &[&sprite_texture_sampler_bind_group_layout],
+            sprite_vertex_shader_source,
+            sprite_fragment_shader_source,
+            BlendState::PREMULTIPLIED_ALPHA_BLENDING,
+            "Sprite (Premultiplied Alpha)",
+        );
+
+        let sprite_outline_shader_info = create_shader_info(
+            device,
+            virtual_texture_format,
+            &camera_bind_group_layout,
+            &[&sprite_texture_sampler_bind_group_layout],
+            sprite_vertex_shader_source,
+            outline_sprite_fragment_source(),
+            alpha_blending,
+            "Sprite (Outline)",
+        );
+
+        let sprite_opaque_shader_info = create_shader_info(
+            device,
+            virtual_texture_format,
+            &camera_bind_group_layout,
+            &[&sprite_texture_sampler_bind_group_layout],
+            sprite_vertex_shader_source,
+            sprite_fragment_shader_source,
+            BlendState::REPLACE,
+            "Sprite (Opaque)",
+        );
+
         // Create quad shader
         let quad_shader_info = {
             let (vertex_shader_source, fragment_shader_source) = quad_shaders();
 
             create_shader_info(
                 device,
-                surface_texture_format,
+                virtual_texture_format,
                 &camera_bind_group_layout,
                 &[],
                 vertex_shader_source,
@@ -347,6 +565,37 @@ impl SpriteInfo {
             )
         };
 
+        // Create circle shader
+        let circle_shader_info = {
+            let (vertex_shader_source, fragment_shader_source) = circle_shaders();
+
+            create_shader_info(
+                device,
+                virtual_texture_format,
+                &camera_bind_group_layout,
+                &[],
+                vertex_shader_source,
+                fragment_shader_source,
+                alpha_blending,
+                "Circle",
+            )
+        };
+
+        let rounded_quad_shader_info = {
+            let (vertex_shader_source, fragment_shader_source) = rounded_quad_shaders();
+
+            create_shader_info(
+                device,
+                virtual_texture_format,
+                &camera_bind_group_layout,
+                &[],
+                vertex_shader_source,
+                fragment_shader_source,
+                alpha_blending,
+                "Rounded Quad",
+            )
+        };
+
         let mask_shader_info = {
             let vertex_shader_source = masked_texture_tinted_vertex_source();
             let fragment_shader_source = masked_texture_tinted_fragment_source();
@@ -359,7 +608,7 @@ impl SpriteInfo {
 
             create_shader_info(
                 device,
-                surface_texture_format,
+                virtual_texture_format,
                 &camera_bind_group_layout,
                 &[&diffuse_texture_group, &alpha_texture_group],
                 vertex_shader_source,
@@ -369,21 +618,55 @@ impl SpriteInfo {
             )
         };
 
+        let screen_effect_uniform_buffer = create_screen_effect_uniform_buffer(
+            device,
+            ScreenEffectUniform::default(),
+            "screen effect uniform buffer",
+        );
+        let screen_effect_bind_group_layout =
+            create_screen_effect_uniform_bind_group_layout(device, "screen effect group layout");
+        let screen_effect_bind_group = create_camera_uniform_bind_group(
+            device,
+            &screen_effect_bind_group_layout,
+            &screen_effect_uniform_buffer,
+            "screen effect bind group",
+        );
+
+        let virtual_texture_group_layout =
+            create_texture_and_sampler_group_layout(device, "virtual texture group");
+
         let virtual_to_screen_shader_info = {
-            let virtual_texture_group_layout =
-                create_texture_and_sampler_group_layout(device, "virtual texture group");
             create_shader_info_ex(
                 device,
                 surface_texture_format,
-                &[&virtual_texture_group_layout],
+                &[
+                    &virtual_texture_group_layout,
+                    &screen_effect_bind_group_layout,
+                ],
                 SCREEN_QUAD_VERTEX_SHADER,
                 SCREEN_QUAD_FRAGMENT_SHADER,
                 &[],
                 alpha_blending,
+                None,
                 "VirtualToScreen",
             )
         };
 
+        let crt_screen_shader_info = create_shader_info_ex(
+            device,
+            surface_texture_format,
+            &[
+                &virtual_texture_group_layout,
+                &screen_effect_bind_group_layout,
+            ],
+            SCREEN_QUAD_VERTEX_SHADER,
+            CRT_SCREEN_QUAD_FRAGMENT_SHADER,
+            &[],
+            alpha_blending,
+            None,
+            "VirtualToScreen (CRT)",
+        );
+
         let light_shader_info = {
             let vertex_shader_source = sprite_vertex_shader_source;
             let fragment_shader_source = sprite_fragment_shader_source;
@@ -391,27 +674,14 @@ impl SpriteInfo {
             let light_texture_group =
                 create_texture_and_sampler_group_layout(device, "light texture group");
 
-            let additive_blend = wgpu::BlendState {
-                color: wgpu::BlendComponent {
-                    src_factor: wgpu::BlendFactor::SrcAlpha,
-                    dst_factor: wgpu::BlendFactor::One,
-                    operation: wgpu::BlendOperation::Add,
-                },
-                alpha: wgpu::BlendComponent {
-                    src_factor: wgpu::BlendFactor::Zero,
-                    dst_factor: wgpu::BlendFactor::One,
-                    operation: wgpu::BlendOperation::Add,
-                },
-            };
-
             create_shader_info(
                 device,
-                surface_texture_format,
+                virtual_texture_format,
                 &camera_bind_group_layout,
                 &[&light_texture_group],
                 vertex_shader_source,
                 fragment_shader_source,
-                additive_blend,
+                additive_blend_state(),
                 "Light (Additive)",
             )
         };
@@ -426,10 +696,19 @@ impl SpriteInfo {
 
         Self {
             sprite_shader_info,
+            sprite_multiply_shader_info,
+            sprite_premultiplied_shader_info,
+            sprite_outline_shader_info,
+            sprite_opaque_shader_info,
             quad_shader_info,
+            circle_shader_info,
+            rounded_quad_shader_info,
             mask_shader_info,
             light_shader_info,
             virtual_to_screen_shader_info,
+            crt_screen_shader_info,
+            screen_effect_uniform_buffer,
+            screen_effect_bind_group,
             sampler,
             vertex_buffer,
             index_buffer,
@@ -442,8 +721,43 @@ impl SpriteInfo {
     }
 }
 
+/// Adds the source color on top of the destination, scaled by source alpha
+#[must_use]
+pub const fn additive_blend_state() -> BlendState {
+    BlendState {
+        color: wgpu::BlendComponent {
+            src_factor: wgpu::BlendFactor::SrcAlpha,
+            dst_factor: wgpu::BlendFactor::One,
+            operation: wgpu::BlendOperation::Add,
+        },
+        alpha: wgpu::BlendComponent {
+            src_factor: wgpu::BlendFactor::Zero,
+            dst_factor: wgpu::BlendFactor::One,
+            operation: wgpu::BlendOperation::Add,
+        },
+    }
+}
+
+/// Multiplies the destination with the source color, transparent source pixels leave the destination as is
+#[must_use]
+pub const fn multiply_blend_state() -> BlendState {
+    BlendState {
+        color: wgpu::BlendComponent {
+            src_factor: wgpu::BlendFactor::Dst,
+            dst_factor: wgpu::BlendFactor::OneMinusSrcAlpha,
+            operation: wgpu::BlendOperation::Add,
+        },
+        alpha: wgpu::BlendComponent {
+            src_factor: wgpu::BlendFactor::Zero,
+            dst_factor: wgpu::BlendFactor::One,
+            operation: wgpu::BlendOperation::Add,
+        },
+    }
+}
+
 /// Creates the view - projection matrix (Camera)
-fn create_camera_uniform_buffer(device: &Device, view_proj: Matrix4, label: &str) -> Buffer {
+#[must_use]
+pub fn create_camera_uniform_buffer(device: &Device, view_proj: Matrix4, label: &str) -> Buffer {
     let camera_uniform = CameraUniform { view_proj };
 
     device.create_buffer_init(&util::BufferInitDescriptor {
@@ -470,7 +784,37 @@ fn create_camera_uniform_bind_group_layout(device: &Device, label: &str) -> Bind
     })
 }
 
-fn create_camera_uniform_bind_group(
+#[must_use]
+pub fn create_screen_effect_uniform_buffer(
+    device: &Device,
+    screen_effect: ScreenEffectUniform,
+    label: &str,
+) -> Buffer {
+    device.create_buffer_init(&util::BufferInitDescriptor {
+        label: Some(label),
+        contents: bytemuck::cast_slice(&[screen_effect]),
+        usage: BufferUsages::UNIFORM | BufferUsages::COPY_DST,
+    })
+}
+
+fn create_screen_effect_uniform_bind_group_layout(device: &Device, label: &str) -> BindGroupLayout {
+    device.create_bind_group_layout(&BindGroupLayoutDescriptor {
+        label: Some(label),
+        entries: &[BindGroupLayoutEntry {
+            binding: 0,
+            visibility: ShaderStages::FRAGMENT,
+            ty: BindingType::Buffer {
+                ty: BufferBindingType::Uniform,
+                has_dynamic_offset: false,
+                min_binding_size: None,
+            },
+            count: None,
+        }],
+    })
+}
+
+#[must_use]
+pub fn create_camera_uniform_bind_group(
     device: &Device,
     bind_group_layout: &BindGroupLayout,
     uniform_buffer: &Buffer,
@@ -486,12 +830,52 @@ fn create_camera_uniform_bind_group(
     })
 }
 
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct TextureLoadOptions {
+    /// Multiplies the color channels with alpha when uploading RGBA images.
+    /// Use together with a premultiplied alpha blend state, to avoid dark halos around
+    /// transparent edges when the texture is filtered.
+    pub premultiply_alpha: bool,
+    /// Creates the full mip chain, so the texture does not shimmer when it is scaled down.
+    /// Should be sampled with a trilinear sampler.
+    pub generate_mipmaps: bool,
+}
+
 #[must_use]
 pub fn load_texture_from_memory(
     device: &Device,
     queue: &Queue,
     img: DynamicImage,
     label: &str,
+) -> Texture {
+    load_texture_from_memory_ex(device, queue, img, label, TextureLoadOptions::default())
+}
+
+/// Creates an `Rgba8UnormSrgb` texture from tightly packed rgba octets, e.g. generated at runtime.
+///
+/// # Panics
+/// If `rgba` is not `width * height * 4` octets.
+#[must_use]
+pub fn load_texture_from_rgba(
+    device: &Device,
+    queue: &Queue,
+    rgba: &[u8],
+    width: u32,
+    height: u32,
+    label: &str,
+) -> Texture {
+    let image = image::RgbaImage::from_raw(width, height, rgba.to_vec())
+        .expect("rgba octets should match the texture size");
+    load_texture_from_memory(device, queue, DynamicImage::ImageRgba8(image), label)
+}
+
+#[must_use]
+pub fn load_texture_from_memory_ex(
+    device: &Device,
+    queue: &Queue,
+    img: DynamicImage,
+    label: &str,
+    options: TextureLoadOptions,
 ) -> Texture {
     let (width, height) = img.dimensions();
     let texture_size = Extent3d {
@@ -557,6 +941,49 @@ pub fn load_texture_from_memory(
         }
     };
 
+    let texture_data =
+        if options.premultiply_alpha && texture_format == TextureFormat::Rgba8UnormSrgb {
+            premultiply_alpha(texture_data)
+        } else {
+            texture_data
+        };
+
+    if options.generate_mipmaps {
+        let mip_level_count = u32::BITS - width.max(height).leading_zeros();
+        let texture = device.create_texture(&TextureDescriptor {
+            label: Some(label),
+            size: texture_size,
+            mip_level_count,
+            sample_count: 1,
+            dimension: TextureDimension::D2,
+            format: texture_format,
+            usage: TextureUsages::TEXTURE_BINDING
+                | TextureUsages::COPY_DST
+                | TextureUsages::RENDER_ATTACHMENT,
+            view_formats: &[texture_format],
+        });
+        let bytes_per_pixel = texture_format
+            .block_copy_size(None)
+            .expect("uncompressed texture format");
+        queue.write_texture(
+            wgpu::TexelCopyTextureInfo {
+                texture: &texture,
+                mip_level: 0,
+                origin: wgpu::Origin3d::ZERO,
+                aspect: wgpu::TextureAspect::All,
+            },
+            &texture_data,
+            wgpu::TexelCopyBufferLayout {
+                offset: 0,
+                bytes_per_row: Some(width * bytes_per_pixel),
+                rows_per_image: Some(height),
+            },
+            texture_size,
+        );
+        generate_mipmaps(device, queue, &texture, label);
+        return texture;
+    }
+
     let texture_descriptor = TextureDescriptor {
         label: Some(label),
         size: texture_size,
@@ -576,6 +1003,113 @@ pub fn load_texture_from_memory(
     )
 }
 
+/// Fills mip level 1 and up of `texture` by rendering each level from the previous one,
+/// with linear filtering. The texture must have `RENDER_ATTACHMENT` usage.
+pub fn generate_mipmaps(device: &Device, queue: &Queue, texture: &Texture, label: &str) {
+    let shader = mireforge_wgpu::create_shader_module(device, "Mipmap", MIPMAP_SHADER);
+    let texture_group_layout =
+        create_texture_and_sampler_group_layout(device, "mipmap texture group");
+    let pipeline_layout =
+        create_pipeline_layout(device, &[&texture_group_layout], "mipmap pipeline layout");
+    let pipeline = create_pipeline_with_buffers(
+        device,
+        texture.format(),
+        &pipeline_layout,
+        &shader,
+        &shader,
+        &[],
+        BlendState::REPLACE,
+        None,
+        PolygonMode::Fill,
+        "Mipmap",
+    );
+    let sampler = mireforge_wgpu::create_trilinear_sampler(device, "mipmap sampler");
+
+    let mip_views: Vec<TextureView> = (0..texture.mip_level_count())
+        .map(|mip_level| {
+            texture.create_view(&TextureViewDescriptor {
+                label: Some(label),
+                base_mip_level: mip_level,
+                mip_level_count: Some(1),
+                ..Default::default()
+            })
+        })
+        .collect();
+
+    let mut encoder = device.create_command_encoder(&wgpu::CommandEncoderDescriptor {
+        label: Some("mipmap encoder"),
+    });
+
+    for pair in mip_views.windows(2) {
+        let bind_group = create_texture_and_sampler_bind_group_ex(
+            device,
+            &texture_group_layout,
+            &pair[0],
+            &sampler,
+            label,
+        );
+        let mut render_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+            label: Some("mipmap pass"),
+            color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+                view: &pair[1],
+                depth_slice: None,
+                resolve_target: None,
+                ops: wgpu::Operations {
+                    load: wgpu::LoadOp::Clear(wgpu::Color::TRANSPARENT),
+                    store: wgpu::StoreOp::Store,
+                },
+            })],
+            depth_stencil_attachment: None,
+            timestamp_writes: None,
+            occlusion_query_set: None,
+            multiview_mask: None,
+        });
+        render_pass.set_pipeline(&pipeline);
+        render_pass.set_bind_group(0, &bind_group, &[]);
+        render_pass.draw(0..3, 0..1);
+    }
+
+    queue.submit(Some(encoder.finish()));
+}
+
+const MIPMAP_SHADER: &str = "
+struct VertexOutput {
+    @builtin(position) position: vec4<f32>,
+    @location(0) tex_coords: vec2<f32>,
+};
+
+@group(0) @binding(0) var source_texture: texture_2d<f32>;
+@group(0) @binding(1) var source_sampler: sampler;
+
+// One triangle that covers the whole target
+@vertex
+fn vs_main(@builtin(vertex_index) vertex_index: u32) -> VertexOutput {
+    let uv = vec2<f32>(f32((vertex_index << 1u) & 2u), f32(vertex_index & 2u));
+
+    var output: VertexOutput;
+    output.position = vec4<f32>(uv * vec2<f32>(2.0, -2.0) + vec2<f32>(-1.0, 1.0), 0.0, 1.0);
+    output.tex_coords = uv;
+    return output;
+}
+
+@fragment
+fn fs_main(input: VertexOutput) -> @location(0) vec4<f32> {
+    return textureSample(source_texture, source_sampler, input.tex_coords);
+}
+";
+
+/// Multiplies the color channels of RGBA octets with the alpha channel, in place.
+#[must_use]
+pub fn premultiply_alpha(mut rgba: Vec<u8>) -> Vec<u8> {
+    for pixel in rgba.chunks_exact_mut(4) {
+        let alpha = u16::from(pixel[3]);
+        for channel in &mut pixel[..3] {
+            *channel = ((u16::from(*channel) * alpha + 127) / 255) as u8;
+        }
+    }
+    rgba
+}
+
 #[must_use]
 pub fn create_sprite_vertex_buffer(device: &Device, label: &str) -> Buffer {
     device.create_buffer_init(&util::BufferInitDescriptor {
@@ -700,6 +1234,8 @@ fn create_pipeline_with_buffers(
     fragment_shader: &ShaderModule,
     buffers: &[VertexBufferLayout],
     blend_state: BlendState,
+    depth_stencil: Option<DepthStencilState>,
+    polygon_mode: PolygonMode,
     label: &str,
 ) -> RenderPipeline {
     device.create_render_pipeline(&RenderPipelineDescriptor {
@@ -727,11 +1263,11 @@ fn create_pipeline_with_buffers(
             front_face: FrontFace::Ccw,
             cull_mode: None,
             unclipped_depth: false,
-            polygon_mode: PolygonMode::Fill,
+            polygon_mode,
             conservative: false,
         },
 
-        depth_stencil: None,
+        depth_stencil,
         multisample: MultisampleState::default(),
         cache: None,
         multiview_mask: None,
@@ -769,6 +1305,8 @@ struct VertexOutput {
     @builtin(position) position: vec4<f32>,
     @location(0) tex_coords: vec2<f32>,
     @location(1) color: vec4<f32>,
+    @location(2) flash: vec4<f32>,
+    @location(3) add_color: vec4<f32>,
 };
 
 // Vertex shader entry point
@@ -783,6 +1321,8 @@ fn vs_main(
     @location(6) tex_multiplier: vec4<f32>,
     @location(7) rotation_step: u32,
     @location(8) color: vec4<f32>,
+    @location(9) flash: vec4<f32>,
+    @location(10) add_color: vec4<f32>,
 ) -> VertexOutput {
     var output: VertexOutput;
 
@@ -830,6 +1370,8 @@ fn vs_main(
     // Modify texture coordinates
     output.tex_coords = rotated_tex_coords * tex_multiplier.xy + tex_multiplier.zw;
     output.color = color;
+    output.flash = flash;
+    output.add_color = add_color;
 
     return output;
 }
@@ -850,6 +1392,8 @@ struct VertexOutput {
     @builtin(position) position: vec4<f32>,
     @location(0) tex_coords: vec2<f32>,
     @location(1) color: vec4<f32>,
+    @location(2) flash: vec4<f32>,
+    @location(3) add_color: vec4<f32>,
 };
 
 // Fragment shader entry point
@@ -858,13 +1402,86 @@ fn fs_main(input: VertexOutput) -> @location(0) vec4<f32> {
     // Sample the texture using the texture coordinates
     let texture_color = textureSample(diffuse_texture, sampler_diffuse, input.tex_coords);
 
-    return texture_color * input.color;
+    let tinted = texture_color * input.color + vec4<f32>(input.add_color.rgb, 0.0);
+
+    // Flash color in rgb and the amount in alpha
+    return vec4<f32>(mix(tinted.rgb, input.flash.rgb, input.flash.a), tinted.a);
 }
 
 ";
     (vertex_shader_source, fragment_shader_source)
 }
 
+/// Same input as the normal sprite fragment shader, but the secondary color is the
+/// outline color instead of the flash. Transparent texels next to an opaque texel get the outline color.
+#[must_use]
+pub const fn outline_sprite_fragment_source() -> &'static str {
+    "
+
+// Bind Group 1: Texture and Sampler
+@group(1) @binding(0)
+var diffuse_texture: texture_2d<f32>;
+
+@group(1) @binding(1)
+var sampler_diffuse: sampler;
+
+// Fragment input structure from vertex shader
+struct VertexOutput {
+    @builtin(position) position: vec4<f32>,
+    @location(0) tex_coords: vec2<f32>,
+    @location(1) color: vec4<f32>,
+    @location(2) outline_color: vec4<f32>,
+    @location(3) add_color: vec4<f32>,
+};
+
+@fragment
+fn fs_main(input: VertexOutput) -> @location(0) vec4<f32> {
+    let texel = 1.0 / vec2<f32>(textureDimensions(diffuse_texture, 0));
+
+    let texture_color = textureSample(diffuse_texture, sampler_diffuse, input.tex_coords);
+    let left = textureSample(diffuse_texture, sampler_diffuse, input.tex_coords - vec2<f32>(texel.x, 0.0)).a;
+    let right = textureSample(diffuse_texture, sampler_diffuse, input.tex_coords + vec2<f32>(texel.x, 0.0)).a;
+    let up = textureSample(diffuse_texture, sampler_diffuse, input.tex_coords - vec2<f32>(0.0, texel.y)).a;
+    let down = textureSample(diffuse_texture, sampler_diffuse, input.tex_coords + vec2<f32>(0.0, texel.y)).a;
+    let neighbor_alpha = step(0.5, max(max(left, right), max(up, down)));
+
+    let tinted = texture_color * input.color + vec4<f32>(input.add_color.rgb, 0.0);
+    let outline_alpha = input.outline_color.a * neighbor_alpha * (1.0 - tinted.a);
+
+    return vec4<f32>(
+        mix(input.outline_color.rgb, tinted.rgb, tinted.a),
+        tinted.a + outline_alpha,
+    );
+}
+
+"
+}
+
+/// Each quad adds a little light, so the brightness shows how many times a pixel is drawn.
+#[must_use]
+pub const fn overdraw_fragment_source() -> &'static str {
+    "
+
+@fragment
+fn fs_main() -> @location(0) vec4<f32> {
+    return vec4<f32>(0.1, 0.05, 0.02, 1.0);
+}
+
+"
+}
+
+#[must_use]
+pub const fn wireframe_fragment_source() -> &'static str {
+    "
+
+@fragment
+fn fs_main() -> @location(0) vec4<f32> {
+    return vec4<f32>(0.0, 1.0, 0.4, 1.0);
+}
+
+"
+}
+
 #[allow(unused)]
 #[must_use]
 pub const fn masked_texture_tinted_fragment_source() -> &'static str {
@@ -955,6 +1572,7 @@ fn vs_main(
     @location(6) tex_multiplier: vec4<f32>,
     @location(7) rotation_step: u32,
     @location(8) color: vec4<f32>,
+    @location(9) mask_offset: vec4<f32>,
 ) -> VertexOutput {
 
     var output: VertexOutput;
@@ -1002,7 +1620,7 @@ fn vs_main(
 
     // Modify texture coordinates
     output.modified_tex_coords = rotated_tex_coords * tex_multiplier.xy + tex_multiplier.zw;
-    output.original_tex_coords = input.tex_coords;
+    output.original_tex_coords = input.tex_coords + mask_offset.xy;
 
     output.color = color;
 
@@ -1024,6 +1642,107 @@ var<uniform> camera_uniforms: Uniforms;
 // Vertex input structure
 struct VertexInput {
     @location(0) position: vec3<f32>,
+    @location(1) tex_coords: vec2<f32>,
+};
+
+// Vertex output structure to fragment shader
+// Must be exactly the same in both places
+struct VertexOutput {
+    @builtin(position) position: vec4<f32>, // MUST BE HERE, DO NOT REMOVE
+    @location(0) color: vec4<f32>,
+    @location(1) tex_coords: vec2<f32>,
+    @location(2) @interpolate(flat) antialias: u32,
+};
+
+// Vertex shader entry point
+@vertex
+fn vs_main(
+    input: VertexInput,
+    // Instance attributes
+    @location(2) model_matrix0: vec4<f32>, // Always fixed
+    @location(3) model_matrix1: vec4<f32>, // Always fixed
+    @location(4) model_matrix2: vec4<f32>, // Always fixed
+    @location(5) model_matrix3: vec4<f32>, // Always fixed
+    @location(7) gradient_direction: u32, // Shares location with rotation
+    @location(8) color: vec4<f32>, //  Always fixed at position 8
+    @location(9) secondary_color: vec4<f32>,
+) -> VertexOutput {
+    var output: VertexOutput;
+
+    // Reconstruct the model matrix from the instance data
+    let model_matrix = mat4x4<f32>(
+        model_matrix0,
+        model_matrix1,
+        model_matrix2,
+        model_matrix3,
+    );
+
+    // Compute world position
+    let world_position = model_matrix * vec4<f32>(input.position, 1.0);
+
+    // Apply view-projection matrix
+    output.position = camera_uniforms.view_proj * world_position;
+
+    // Texture coordinates have origin in the upper left, so flip y to go from bottom to top.
+    // For solid quads, color and secondary_color are the same.
+    // Bit 0 is the gradient direction, bit 1 enables antialiasing of the edges
+    var gradient_factor = 1.0 - input.tex_coords.y;
+    if ((gradient_direction & 1u) == 1u) {
+        gradient_factor = input.tex_coords.x;
+    }
+    output.color = mix(color, secondary_color, gradient_factor);
+    output.tex_coords = input.tex_coords;
+    output.antialias = (gradient_direction >> 1u) & 1u;
+
+    return output;
+}
+        ";
+    //
+
+    let fragment_shader_source = "
+
+// Fragment input structure from vertex shader,
+// Must be exactly the same in both places
+struct VertexOutput {
+    @builtin(position) position: vec4<f32>, // MUST BE HERE, DO NOT REMOVE
+    @location(0) color: vec4<f32>,
+    @location(1) tex_coords: vec2<f32>,
+    @location(2) @interpolate(flat) antialias: u32,
+};
+
+// Fragment shader entry point
+@fragment
+fn fs_main(input: VertexOutput) -> @location(0) vec4<f32> {
+    if (input.antialias == 0u) {
+        return input.color;
+    }
+
+    // Fades out the outermost pixel, so rotated and float scaled edges are smooth
+    let pixels_to_edge = min(input.tex_coords, vec2<f32>(1.0, 1.0) - input.tex_coords) / fwidth(input.tex_coords);
+    let coverage = clamp(min(pixels_to_edge.x, pixels_to_edge.y), 0.0, 1.0);
+
+    return vec4<f32>(input.color.rgb, input.color.a * coverage);
+}
+
+";
+    (vertex_shader_source, fragment_shader_source)
+}
+
+const fn circle_shaders() -> (&'static str, &'static str) {
+    let vertex_shader_source = "
+// Bind Group 0: Uniforms (view-projection matrix)
+struct Uniforms {
+    view_proj: mat4x4<f32>,
+};
+// Camera (view projection matrix) is always first
+@group(0) @binding(0)
+var<uniform> camera_uniforms: Uniforms;
+
+
+// Vertex input structure
+struct VertexInput {
+    @location(0) position: vec3<f32>,
+    @location(1) tex_coords: vec2<f32>,
 };
 
 // Vertex output structure to fragment shader
@@ -1031,6 +1750,7 @@ struct VertexInput {
 struct VertexOutput {
     @builtin(position) position: vec4<f32>, // MUST BE HERE, DO NOT REMOVE
     @location(0) color: vec4<f32>,
+    @location(1) tex_coords: vec2<f32>,
 };
 
 // Vertex shader entry point
@@ -1060,6 +1780,7 @@ fn vs_main(
     // Apply view-projection matrix
     output.position = camera_uniforms.view_proj * world_position;
     output.color = color;
+    output.tex_coords = input.tex_coords;
 
     return output;
 }
@@ -1073,12 +1794,19 @@ fn vs_main(
 struct VertexOutput {
     @builtin(position) position: vec4<f32>, // MUST BE HERE, DO NOT REMOVE
     @location(0) color: vec4<f32>,
+    @location(1) tex_coords: vec2<f32>,
 };
 
 // Fragment shader entry point
 @fragment
 fn fs_main(input: VertexOutput) -> @location(0) vec4<f32> {
-    // It is a quad, so we only use the color
+    // Map the quad to -1.0..1.0 and discard everything outside the unit circle,
+    // the quad scale turns it into an ellipse
+    let from_center = input.tex_coords * 2.0 - vec2<f32>(1.0, 1.0);
+    if (dot(from_center, from_center) > 1.0) {
+        discard;
+    }
+
     return input.color;
 }
 
@@ -1086,6 +1814,104 @@ fn fs_main(input: VertexOutput) -> @location(0) vec4<f32> {
     (vertex_shader_source, fragment_shader_source)
 }
 
+/// A quad with rounded corners. The distance to the rounded rectangle is computed per fragment,
+/// from the size and radius in the secondary color, and used for one pixel of antialiasing.
+const fn rounded_quad_shaders() -> (&'static str, &'static str) {
+    let vertex_shader_source = "
+// Bind Group 0: Uniforms (view-projection matrix)
+struct Uniforms {
+    view_proj: mat4x4<f32>,
+};
+// Camera (view projection matrix) is always first
+@group(0) @binding(0)
+var<uniform> camera_uniforms: Uniforms;
+
+// Vertex input structure
+struct VertexInput {
+    @location(0) position: vec3<f32>,
+    @location(1) tex_coords: vec2<f32>,
+};
+
+// Vertex output structure to fragment shader
+// Must be exactly the same in both places
+struct VertexOutput {
+    @builtin(position) position: vec4<f32>, // MUST BE HERE, DO NOT REMOVE
+    @location(0) color: vec4<f32>,
+    @location(1) tex_coords: vec2<f32>,
+    @location(2) size_and_radius: vec4<f32>,
+};
+
+// Vertex shader entry point
+@vertex
+fn vs_main(
+    input: VertexInput,
+    // Instance attributes
+    @location(2) model_matrix0: vec4<f32>, // Always fixed
+    @location(3) model_matrix1: vec4<f32>, // Always fixed
+    @location(4) model_matrix2: vec4<f32>, // Always fixed
+    @location(5) model_matrix3: vec4<f32>, // Always fixed
+    @location(8) color: vec4<f32>, //  Always fixed at position 8
+    @location(9) size_and_radius: vec4<f32>,
+) -> VertexOutput {
+    var output: VertexOutput;
+
+    // Reconstruct the model matrix from the instance data
+    let model_matrix = mat4x4<f32>(
+        model_matrix0,
+        model_matrix1,
+        model_matrix2,
+        model_matrix3,
+    );
+
+    // Compute world position
+    let world_position = model_matrix * vec4<f32>(input.position, 1.0);
+
+    // Apply view-projection matrix
+    output.position = camera_uniforms.view_proj * world_position;
+    output.color = color;
+    output.tex_coords = input.tex_coords;
+    output.size_and_radius = size_and_radius;
+
+    return output;
+}
+        ";
+    //
+
+    let fragment_shader_source = "
+
+// Fragment input structure from vertex shader,
+// Must be exactly the same in both places
+struct VertexOutput {
+    @builtin(position) position: vec4<f32>, // MUST BE HERE, DO NOT REMOVE
+    @location(0) color: vec4<f32>,
+    @location(1) tex_coords: vec2<f32>,
+    @location(2) size_and_radius: vec4<f32>,
+};
+
+// Fragment shader entry point
+@fragment
+fn fs_main(input: VertexOutput) -> @location(0) vec4<f32> {
+    let size = input.size_and_radius.xy;
+    let half_size = size * 0.5;
+    let radius = min(input.size_and_radius.z, min(half_size.x, half_size.y));
+
+    // Signed distance from the rounded rectangle, in pixels, negative inside
+    let from_center = input.tex_coords * size - half_size;
+    let corner = abs(from_center) - half_size + vec2<f32>(radius, radius);
+    let distance = length(max(corner, vec2<f32>(0.0, 0.0))) + min(max(corner.x, corner.y), 0.0) - radius;
+
+    let coverage = clamp(0.5 - distance, 0.0, 1.0);
+    if (coverage <= 0.0) {
+        discard;
+    }
+
+    return vec4<f32>(input.color.rgb, input.color.a * coverage);
+}
+
+";
+    (vertex_shader_source, fragment_shader_source)
+}
+
 pub const SCREEN_QUAD_VERTEX_SHADER: &str = "
 // Define the output structure
 struct VertexOutput {
@@ -1126,8 +1952,65 @@ pub const SCREEN_QUAD_FRAGMENT_SHADER: &str = "
 @group(0) @binding(0) var game_texture: texture_2d<f32>;
 @group(0) @binding(1) var game_sampler: sampler;
 
+struct ScreenEffect {
+    saturation: f32,
+    brightness: f32,
+    crt_intensity: f32,
+    time: f32,
+    virtual_size: vec2<f32>,
+    padding: vec2<f32>,
+};
+
+@group(1) @binding(0) var<uniform> screen_effect: ScreenEffect;
+
 @fragment
 fn fs_main(@location(0) texcoord: vec2<f32>) -> @location(0) vec4<f32> {
-    return textureSample(game_texture, game_sampler, texcoord);
+    let color = textureSample(game_texture, game_sampler, texcoord);
+    let luminance = dot(color.rgb, vec3<f32>(0.2126, 0.7152, 0.0722));
+    let saturated = mix(vec3<f32>(luminance), color.rgb, screen_effect.saturation);
+
+    return vec4<f32>(saturated * screen_effect.brightness, color.a);
+}
+";
+
+/// Same bindings as [`SCREEN_QUAD_FRAGMENT_SHADER`], but with scanlines and barrel distortion.
+pub const CRT_SCREEN_QUAD_FRAGMENT_SHADER: &str = "
+@group(0) @binding(0) var game_texture: texture_2d<f32>;
+@group(0) @binding(1) var game_sampler: sampler;
+
+struct ScreenEffect {
+    saturation: f32,
+    brightness: f32,
+    crt_intensity: f32,
+    time: f32,
+    virtual_size: vec2<f32>,
+    padding: vec2<f32>,
+};
+
+@group(1) @binding(0) var<uniform> screen_effect: ScreenEffect;
+
+const PI: f32 = 3.14159265;
+
+@fragment
+fn fs_main(@location(0) texcoord: vec2<f32>) -> @location(0) vec4<f32> {
+    let intensity = screen_effect.crt_intensity;
+
+    // Barrel distortion, pushes the texture coordinates outwards towards the corners
+    let centered = texcoord - vec2<f32>(0.5);
+    let distorted = texcoord + centered * dot(centered, centered) * 0.2 * intensity;
+
+    // Sample before the branch, textureSample must be in uniform control flow
+    let color = textureSample(game_texture, game_sampler, distorted);
+
+    if (any(distorted < vec2<f32>(0.0)) || any(distorted > vec2<f32>(1.0))) {
+        return vec4<f32>(0.0, 0.0, 0.0, 1.0);
+    }
+
+    // One dark line per virtual pixel row, slowly rolling downwards
+    let row = distorted.y * screen_effect.virtual_size.y + screen_effect.time * 2.0;
+    let scanline = 0.5 + 0.5 * cos(row * 2.0 * PI);
+    let darkening = 1.0 - intensity * 0.4 * (1.0 - scanline);
+
+    return vec4<f32>(color.rgb * darkening * screen_effect.brightness, color.a);
 }
 ";
@@ -15,6 +15,45 @@ pub fn create_shader_module(device: &Device, name: &str, shader_source: &str) ->
     })
 }
 
+/// Linear filtering between texels and between mip levels. Meant for textures with mipmaps
+/// that are scaled down, not for pixel art.
+#[must_use]
+pub fn create_trilinear_sampler(device: &Device, label: &str) -> Sampler {
+    device.create_sampler(&SamplerDescriptor {
+        label: Some(label),
+        address_mode_u: AddressMode::Repeat,
+        address_mode_v: AddressMode::Repeat,
+        address_mode_w: AddressMode::Repeat,
+        mag_filter: FilterMode::Linear,
+        min_filter: FilterMode::Linear,
+        mipmap_filter: MipmapFilterMode::Linear,
+        compare: None,
+        anisotropy_clamp: 1,
+        lod_min_clamp: 0.0,
+        lod_max_clamp: 32.0,
+        border_color: None,
+    })
+}
+
+/// Linear filtering that clamps to the edges, for a single texture that is scaled up as a whole.
+#[must_use]
+pub fn create_linear_clamp_sampler(device: &Device, label: &str) -> Sampler {
+    device.create_sampler(&SamplerDescriptor {
+        label: Some(label),
+        address_mode_u: AddressMode::ClampToEdge,
+        address_mode_v: AddressMode::ClampToEdge,
+        address_mode_w: AddressMode::ClampToEdge,
+        mag_filter: FilterMode::Linear,
+        min_filter: FilterMode::Linear,
+        mipmap_filter: MipmapFilterMode::Nearest,
+        compare: None,
+        anisotropy_clamp: 1,
+        lod_min_clamp: 0.0,
+        lod_max_clamp: 32.0,
+        border_color: None,
+    })
+}
+
 #[must_use]
 pub fn create_nearest_sampler(device: &Device, label: &str) -> Sampler {
     device.create_sampler(&SamplerDescriptor {